@@ -1,9 +1,8 @@
 extern crate coap;
 
-use coap::client::ObserveMessage;
+use coap::client::{ClientError, ObserveMessage};
 use coap::UdpCoAPClient;
 use std::io;
-use std::io::ErrorKind;
 
 #[tokio::main]
 async fn main() {
@@ -34,13 +33,10 @@ async fn example_get() {
                 String::from_utf8(response.message.payload).unwrap()
             );
         }
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::WouldBlock => println!("Request timeout"), // Unix
-                ErrorKind::TimedOut => println!("Request timeout"),   // Windows
-                _ => println!("Request error: {:?}", e),
-            }
-        }
+        Err(e) => match e {
+            ClientError::Timeout => println!("Request timeout"),
+            _ => println!("Request error: {:?}", e),
+        },
     }
 }
 
@@ -56,13 +52,10 @@ async fn example_post() {
                 String::from_utf8(response.message.payload).unwrap()
             );
         }
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::WouldBlock => println!("Request timeout"), // Unix
-                ErrorKind::TimedOut => println!("Request timeout"),   // Windows
-                _ => println!("Request error: {:?}", e),
-            }
-        }
+        Err(e) => match e {
+            ClientError::Timeout => println!("Request timeout"),
+            _ => println!("Request error: {:?}", e),
+        },
     }
 }
 
@@ -78,13 +71,10 @@ async fn example_put() {
                 String::from_utf8(response.message.payload).unwrap()
             );
         }
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::WouldBlock => println!("Request timeout"), // Unix
-                ErrorKind::TimedOut => println!("Request timeout"),   // Windows
-                _ => println!("Request error: {:?}", e),
-            }
-        }
+        Err(e) => match e {
+            ClientError::Timeout => println!("Request timeout"),
+            _ => println!("Request error: {:?}", e),
+        },
     }
 }
 
@@ -99,13 +89,10 @@ async fn example_delete() {
                 String::from_utf8(response.message.payload).unwrap()
             );
         }
-        Err(e) => {
-            match e.kind() {
-                ErrorKind::WouldBlock => println!("Request timeout"), // Unix
-                ErrorKind::TimedOut => println!("Request timeout"),   // Windows
-                _ => println!("Request error: {:?}", e),
-            }
-        }
+        Err(e) => match e {
+            ClientError::Timeout => println!("Request timeout"),
+            _ => println!("Request error: {:?}", e),
+        },
     }
 }
 
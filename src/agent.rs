@@ -0,0 +1,223 @@
+use std::{collections::HashMap, time::Duration};
+
+use coap_lite::{CoapResponse, RequestType as Method};
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::client::{ClientConfig, ClientError, CoapUrl, Scheme, SchemeClient};
+
+/// the scheme, host and port a client is connected to
+type Origin = (Scheme, String, u16);
+
+struct CachedClient {
+    client: SchemeClient,
+    last_used: Instant,
+}
+
+/// Sends requests to coap urls like the static helpers of `CoAPClient`, but keeps one client
+/// per scheme, host and port. Requests to the same server reuse the socket, the receive loop,
+/// the resolved address and the message id state. Clients unused for longer than the idle
+/// timeout are dropped
+///
+/// # Examples
+///
+/// ```no_run
+/// # tokio_test::block_on(async {
+/// use coap::CoapAgent;
+///
+/// let agent = CoapAgent::new();
+/// for _ in 0..10 {
+///     let response = agent.get("coap://127.0.0.1:5683/temperature").await.unwrap();
+///     println!("{:?}", response.message.payload);
+/// }
+/// # })
+/// ```
+pub struct CoapAgent {
+    clients: Mutex<HashMap<Origin, CachedClient>>,
+    idle_timeout: Duration,
+    config: ClientConfig,
+}
+
+impl Default for CoapAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoapAgent {
+    const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+    pub fn new() -> Self {
+        Self::with_idle_timeout(Self::DEFAULT_IDLE_TIMEOUT)
+    }
+
+    /// Create an agent which drops clients unused for longer than `idle_timeout`
+    pub fn with_idle_timeout(idle_timeout: Duration) -> Self {
+        Self {
+            clients: Mutex::new(HashMap::new()),
+            idle_timeout,
+            config: ClientConfig::default(),
+        }
+    }
+
+    /// Set the config used for all requests, e.g. the DTLS config for coaps urls
+    pub fn config(mut self, config: ClientConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Execute a get request with a coap url
+    pub async fn get(&self, url: &str) -> Result<CoapResponse, ClientError> {
+        self.request(url, Method::Get, None).await
+    }
+
+    /// Execute a post request with a coap url
+    pub async fn post(&self, url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        self.request(url, Method::Post, Some(data)).await
+    }
+
+    /// Execute a put request with a coap url
+    pub async fn put(&self, url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        self.request(url, Method::Put, Some(data)).await
+    }
+
+    /// Execute a delete request with a coap url
+    pub async fn delete(&self, url: &str) -> Result<CoapResponse, ClientError> {
+        self.request(url, Method::Delete, None).await
+    }
+
+    /// Execute a fetch request with a coap url
+    pub async fn fetch(&self, url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        self.request(url, Method::Fetch, Some(data)).await
+    }
+
+    /// Execute a patch request with a coap url
+    pub async fn patch(&self, url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        self.request(url, Method::Patch, Some(data)).await
+    }
+
+    /// Execute an ipatch request with a coap url
+    pub async fn ipatch(&self, url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        self.request(url, Method::IPatch, Some(data)).await
+    }
+
+    /// Execute a request with a coap url
+    pub async fn request(
+        &self,
+        url: &str,
+        method: Method,
+        data: Option<Vec<u8>>,
+    ) -> Result<CoapResponse, ClientError> {
+        self.request_with_optional_timeout(url, method, data, None)
+            .await
+    }
+
+    /// Execute a request with a coap url and a specific timeout
+    pub async fn request_with_timeout(
+        &self,
+        url: &str,
+        method: Method,
+        data: Option<Vec<u8>>,
+        timeout: Duration,
+    ) -> Result<CoapResponse, ClientError> {
+        self.request_with_optional_timeout(url, method, data, Some(timeout))
+            .await
+    }
+
+    async fn request_with_optional_timeout(
+        &self,
+        url: &str,
+        method: Method,
+        data: Option<Vec<u8>>,
+        timeout: Option<Duration>,
+    ) -> Result<CoapResponse, ClientError> {
+        let (server, request) = self.config.route(url, method, data)?;
+        let client = self.client_for(&server).await?;
+        // the timeout only applies to this request
+        let receive_timeout = timeout.or(self.config.receive_timeout);
+        let response = client.send(request, receive_timeout).await?;
+        self.config.check_response(response)
+    }
+
+    /// returns the cached client for the origin of the server, or connects a new one
+    async fn client_for(&self, url: &CoapUrl) -> Result<SchemeClient, ClientError> {
+        let origin = (url.scheme, url.host.clone(), url.port);
+        {
+            let mut clients = self.clients.lock().await;
+            let now = Instant::now();
+            let idle_timeout = self.idle_timeout;
+            clients.retain(|_, cached| now.duration_since(cached.last_used) < idle_timeout);
+            if let Some(cached) = clients.get_mut(&origin) {
+                cached.last_used = now;
+                return Ok(cached.client.clone());
+            }
+        }
+        // connecting may resolve the host or do a DTLS handshake, requests to other
+        // origins must not wait for it
+        let client = SchemeClient::connect(url, &self.config).await?;
+        let mut clients = self.clients.lock().await;
+        // a concurrent request may have connected to the origin in the meantime
+        let cached = clients.entry(origin).or_insert(CachedClient {
+            client,
+            last_used: Instant::now(),
+        });
+        cached.last_used = Instant::now();
+        Ok(cached.client.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::test::{ack, spawn_udp_server};
+    use coap_lite::ResponseType as Status;
+
+    /// answers every request with the port it was sent from
+    async fn spawn_source_port_server() -> u16 {
+        let server_addr = spawn_udp_server(|request, source| {
+            let mut response = ack(&request, Status::Content);
+            response.payload = source.port().to_be_bytes().to_vec();
+            Some(response)
+        })
+        .await;
+        server_addr.port()
+    }
+
+    #[tokio::test]
+    async fn test_agent_reuses_clients() {
+        let server_port = spawn_source_port_server().await;
+        let url = format!("coap://127.0.0.1:{}/source", server_port);
+        let agent = CoapAgent::new();
+
+        let first = agent.get(&url).await.unwrap();
+        let second = agent
+            .request_with_timeout(&url, Method::Get, None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(first.message.payload, second.message.payload);
+        assert_eq!(agent.clients.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_agent_concurrent_requests_share_a_client() {
+        let server_port = spawn_source_port_server().await;
+        let url = format!("coap://127.0.0.1:{}/source", server_port);
+        let agent = CoapAgent::new();
+
+        let (first, second) = tokio::join!(agent.get(&url), agent.get(&url));
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(agent.clients.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_agent_evicts_idle_clients() {
+        let server_port = spawn_source_port_server().await;
+        let url = format!("coap://127.0.0.1:{}/source", server_port);
+        let agent = CoapAgent::with_idle_timeout(Duration::from_millis(50));
+
+        let first = agent.get(&url).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let second = agent.get(&url).await.unwrap();
+        assert_ne!(first.message.payload, second.message.payload);
+    }
+}
@@ -0,0 +1,367 @@
+//! A client-side response cache as described in RFC 7252 section 5.6
+
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use async_trait::async_trait;
+use coap_lite::{
+    CoapOption, CoapRequest, CoapResponse, MessageClass, Packet, RequestType as Method,
+    ResponseType as Status,
+};
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::client::{encode_uint, ClientError, Interceptor};
+use crate::response::ResponseExt;
+
+/// the options a cached response is looked up by, those identifying the resource first.
+/// ETag, Observe, the Block options and the NoCacheKey options like Size1 are left out,
+/// RFC 7252 section 5.4.6
+const CACHE_KEY_OPTIONS: [CoapOption; 7] = [
+    CoapOption::UriHost,
+    CoapOption::UriPort,
+    CoapOption::UriPath,
+    CoapOption::ProxyUri,
+    CoapOption::ProxyScheme,
+    CoapOption::UriQuery,
+    CoapOption::Accept,
+];
+/// the number of cache key options identifying the resource
+const RESOURCE_OPTIONS: usize = 5;
+
+/// the values of each cache key option, in the order of `CACHE_KEY_OPTIONS`
+type CacheKey = Vec<Vec<Vec<u8>>>;
+
+struct CachedResponse {
+    message: Packet,
+    expires: Instant,
+}
+
+/// Caches the responses to GET requests of a client. Responses are served from the cache
+/// until their Max-Age passes, stale responses with an ETag are revalidated with the server
+/// and a 2.03 Valid makes them fresh again. Successful POST, PUT, DELETE, PATCH and iPATCH
+/// requests invalidate the cached responses of their resource
+///
+/// # Examples
+///
+/// ```no_run
+/// # tokio_test::block_on(async {
+/// use coap::cache::ResponseCache;
+/// use coap::request::{Method, RequestBuilder};
+/// use coap::UdpCoAPClient;
+///
+/// let mut client = UdpCoAPClient::new_udp("127.0.0.1:5683").await.unwrap();
+/// client.add_interceptor(ResponseCache::new());
+/// for _ in 0..10 {
+///     // only sent to the server once the Max-Age of the last response passed
+///     let request = RequestBuilder::new("/config", Method::Get).build();
+///     client.send(request).await.unwrap();
+/// }
+/// # })
+/// ```
+pub struct ResponseCache {
+    entries: Mutex<HashMap<CacheKey, CachedResponse>>,
+    capacity: usize,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResponseCache {
+    const DEFAULT_CAPACITY: usize = 256;
+
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Create a cache holding at most `capacity` responses. When it is full, the response
+    /// closest to expiring is dropped
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+        }
+    }
+
+    fn cache_key(request: &CoapRequest<SocketAddr>) -> CacheKey {
+        CACHE_KEY_OPTIONS
+            .into_iter()
+            .map(|option| {
+                request
+                    .message
+                    .get_option(option)
+                    .map(|values| values.iter().cloned().collect())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    fn is_cacheable(request: &CoapRequest<SocketAddr>) -> bool {
+        *request.get_method() == Method::Get
+            && request
+                .message
+                .get_first_option(CoapOption::Observe)
+                .is_none()
+    }
+
+    fn is_unsafe(method: Method) -> bool {
+        matches!(
+            method,
+            Method::Post | Method::Put | Method::Delete | Method::Patch | Method::IPatch
+        )
+    }
+
+    /// a copy of the cached response, with the remaining freshness as its Max-Age
+    fn serve(message: &Packet, fresh_for: Duration) -> CoapResponse {
+        let mut message = message.clone();
+        message.clear_option(CoapOption::MaxAge);
+        message.add_option(CoapOption::MaxAge, encode_uint(fresh_for.as_secs()));
+        CoapResponse { message }
+    }
+
+    /// whether the response is a copy of the cached one served by `on_request`. Responses
+    /// from the server carry the message id and token of their own request
+    fn is_served(cached: &Packet, response: &Packet) -> bool {
+        cached.header.message_id == response.header.message_id
+            && cached.get_token() == response.get_token()
+            && cached.payload == response.payload
+    }
+
+    fn store(
+        &self,
+        entries: &mut HashMap<CacheKey, CachedResponse>,
+        key: CacheKey,
+        message: Packet,
+        max_age: Duration,
+    ) {
+        if !entries.contains_key(&key) && entries.len() >= self.capacity {
+            let expiring = entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.expires)
+                .map(|(key, _)| key.clone());
+            if let Some(expiring) = expiring {
+                entries.remove(&expiring);
+            }
+        }
+        // a Max-Age beyond what the clock can represent is not cached at all
+        let Some(expires) = Instant::now().checked_add(max_age) else {
+            entries.remove(&key);
+            return;
+        };
+        // responses with a Max-Age of 0 are stored stale, so that they can be revalidated
+        entries.insert(key, CachedResponse { message, expires });
+    }
+}
+
+#[async_trait]
+impl Interceptor for ResponseCache {
+    async fn on_request(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+    ) -> Result<Option<CoapResponse>, ClientError> {
+        if !Self::is_cacheable(request) {
+            return Ok(None);
+        }
+        let key = Self::cache_key(request);
+        let mut entries = self.entries.lock().await;
+        let Some(cached) = entries.get(&key) else {
+            return Ok(None);
+        };
+        let now = Instant::now();
+        if cached.expires > now {
+            return Ok(Some(Self::serve(&cached.message, cached.expires - now)));
+        }
+        // a stale response is revalidated with its ETag, RFC 7252 section 5.6.2
+        match cached.message.get_first_option(CoapOption::ETag) {
+            Some(etag) => {
+                if request.message.get_first_option(CoapOption::ETag).is_none() {
+                    request.message.add_option(CoapOption::ETag, etag.clone());
+                }
+            }
+            None => {
+                entries.remove(&key);
+            }
+        }
+        Ok(None)
+    }
+
+    async fn on_response(
+        &self,
+        request: &CoapRequest<SocketAddr>,
+        response: &mut CoapResponse,
+    ) -> Result<(), ClientError> {
+        let MessageClass::Response(status) = response.message.header.code else {
+            return Ok(());
+        };
+        let key = Self::cache_key(request);
+        let mut entries = self.entries.lock().await;
+        if Self::is_unsafe(*request.get_method()) {
+            // the cached representations of a created, deleted or changed resource are
+            // outdated, RFC 7252 section 5.9.1
+            if response.is_success() {
+                entries.retain(|cached, _| cached[..RESOURCE_OPTIONS] != key[..RESOURCE_OPTIONS]);
+            }
+            return Ok(());
+        }
+        if !Self::is_cacheable(request) {
+            return Ok(());
+        }
+        match status {
+            Status::Content => {
+                // storing a response served from the cache again would round its expiry
+                // down to the second
+                if let Some(cached) = entries.get(&key) {
+                    if Self::is_served(&cached.message, &response.message) {
+                        return Ok(());
+                    }
+                }
+                self.store(
+                    &mut entries,
+                    key,
+                    response.message.clone(),
+                    response.max_age(),
+                );
+            }
+            Status::Valid => {
+                let Some(cached) = entries.get(&key) else {
+                    return Ok(());
+                };
+                let cached_etag = cached.message.get_first_option(CoapOption::ETag);
+                if cached_etag != response.message.get_first_option(CoapOption::ETag) {
+                    return Ok(());
+                }
+                let max_age = response.max_age();
+                let message = cached.message.clone();
+                *response = Self::serve(&message, max_age);
+                self.store(&mut entries, key, message, max_age);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::UdpCoAPClient;
+    use crate::request::RequestBuilder;
+    use crate::server::test::{ack, spawn_udp_server};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// serves `/config` with the ETag "v1" and the Max-Age, answers 2.03 to requests with that
+    /// ETag. Returns a caching client, the number of GETs and the number of revalidations
+    async fn spawn_config_server(
+        max_age: u8,
+    ) -> (UdpCoAPClient, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let gets = Arc::new(AtomicUsize::new(0));
+        let validations = Arc::new(AtomicUsize::new(0));
+        let (server_gets, server_validations) = (gets.clone(), validations.clone());
+        let server_addr = spawn_udp_server(move |request, _| {
+            if request.header.code == MessageClass::Request(Method::Get) {
+                server_gets.fetch_add(1, Ordering::Relaxed);
+            }
+            let valid = request.get_first_option(CoapOption::ETag) == Some(&b"v1".to_vec());
+            let mut response = if valid {
+                server_validations.fetch_add(1, Ordering::Relaxed);
+                let mut response = ack(&request, Status::Valid);
+                response.add_option(CoapOption::MaxAge, vec![60]);
+                response
+            } else {
+                let mut response = ack(&request, Status::Content);
+                response.add_option(CoapOption::MaxAge, vec![max_age]);
+                response.payload = b"config".to_vec();
+                response
+            };
+            response.add_option(CoapOption::ETag, b"v1".to_vec());
+            Some(response)
+        })
+        .await;
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.add_interceptor(ResponseCache::new());
+        (client, gets, validations)
+    }
+
+    async fn get(client: &UdpCoAPClient, path: &str) -> CoapResponse {
+        client
+            .send(RequestBuilder::new(path, Method::Get).build())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fresh_responses_are_cached() {
+        let (client, gets, _) = spawn_config_server(60).await;
+        let first = get(&client, "/config").await;
+        let second = get(&client, "/config").await;
+        assert_eq!(first.message.payload, b"config");
+        assert_eq!(second.message.payload, b"config");
+        assert_eq!(*second.get_status(), Status::Content);
+        assert!(second.max_age() <= Duration::from_secs(60));
+        assert_eq!(gets.load(Ordering::Relaxed), 1);
+
+        let request = RequestBuilder::new("/config", Method::Get)
+            .queries(Some(b"unit=c".to_vec()))
+            .build();
+        client.send(request).await.unwrap();
+        assert_eq!(gets.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_hits_keep_the_expiry() {
+        let cache = ResponseCache::new();
+        let mut request = RequestBuilder::new("/config", Method::Get).build();
+        let mut message = Packet::new();
+        message.header.code = MessageClass::Response(Status::Content);
+        message.header.message_id = 1;
+        message.set_token(vec![1]);
+        message.add_option(CoapOption::MaxAge, vec![60]);
+        cache
+            .on_response(&request, &mut CoapResponse { message })
+            .await
+            .unwrap();
+        let key = ResponseCache::cache_key(&request);
+        let expires = cache.entries.lock().await[&key].expires;
+
+        for _ in 0..3 {
+            let mut served = cache.on_request(&mut request).await.unwrap().unwrap();
+            cache.on_response(&request, &mut served).await.unwrap();
+        }
+        assert_eq!(cache.entries.lock().await[&key].expires, expires);
+    }
+
+    #[tokio::test]
+    async fn test_stale_responses_are_revalidated() {
+        let (client, gets, validations) = spawn_config_server(0).await;
+        get(&client, "/config").await;
+        // stale right away, the server confirms it is still valid
+        let revalidated = get(&client, "/config").await;
+        assert_eq!(*revalidated.get_status(), Status::Content);
+        assert_eq!(revalidated.message.payload, b"config");
+        assert_eq!(revalidated.max_age(), Duration::from_secs(60));
+        // fresh again for the Max-Age of the 2.03
+        get(&client, "/config").await;
+        assert_eq!(gets.load(Ordering::Relaxed), 2);
+        assert_eq!(validations.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unsafe_methods_invalidate() {
+        let (client, gets, _) = spawn_config_server(60).await;
+        get(&client, "/config").await;
+        client
+            .send(
+                RequestBuilder::new("/config", Method::Put)
+                    .data(Some(b"new".to_vec()))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        get(&client, "/config").await;
+        assert_eq!(gets.load(Ordering::Relaxed), 2);
+    }
+}
@@ -1,41 +1,121 @@
 #[cfg(feature = "dtls")]
 use crate::dtls::{DtlsConnection, UdpDtlsConfig};
+use crate::link_format::{self, Link};
+use crate::payload::PayloadError;
 use crate::request::RequestBuilder;
+use crate::response::ResponseExt;
 use alloc::string::String;
 use alloc::vec::Vec;
 use coap_lite::{
     block_handler::{extending_splice, BlockValue},
-    error::HandlingError,
+    error::MessageError,
     CoapOption, CoapRequest, CoapResponse, MessageClass, MessageType, ObserveOption, Packet as Message,
     RequestType as Method, ResponseType as Status,
 };
 use core::mem;
 
-use futures::Future;
+use futures::{stream, Future, Stream, TryStreamExt};
 use log::*;
 
 use regex::Regex;
 use std::{
-    collections::BTreeMap,
+    collections::{btree_map::Entry, BTreeMap, HashMap, HashSet},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
-    sync::{atomic::AtomicU16, Weak},
+    sync::{
+        atomic::{AtomicU16, AtomicUsize, Ordering},
+        Weak,
+    },
 };
 use std::{
+    fmt,
     io::{Error, ErrorKind, Result as IoResult},
     pin::Pin,
+    task::{Context, Poll},
 };
 use std::{sync::Arc, time::Duration};
 use tokio::sync::{
     mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-    oneshot, Mutex,
+    oneshot, watch, Mutex, Semaphore,
 };
-use tokio::time::timeout;
+use tokio::time::{timeout, timeout_at, Instant};
 use tokio::{
     net::{lookup_host, ToSocketAddrs, UdpSocket},
     sync::RwLock,
 };
 use url::Url;
 const DEFAULT_RECEIVE_TIMEOUT_SECONDS: u64 = 2; // 2s
+const DEFAULT_ACK_RANDOM_FACTOR: f64 = 1.5;
+const DEFAULT_MAX_RETRANSMIT: usize = 4;
+const DEFAULT_NSTART: usize = 1;
+const DEFAULT_MAX_AGE_SECONDS: u64 = 60;
+// notifications sent right when the Max-Age runs out still arrive in time
+const OBSERVE_REREGISTRATION_LEEWAY: Duration = Duration::from_secs(2);
+// re-registrations later than the clock can represent are scheduled this far ahead instead
+const OBSERVE_DISTANT_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+const MAX_LATENCY_SECONDS: u64 = 100;
+
+/// Transmission parameters used for confirmable messages, see
+/// [RFC 7252 section 4.8](https://tools.ietf.org/html/rfc7252#section-4.8)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransmissionParameters {
+    /// ACK_TIMEOUT, the base time to wait for an acknowledgement
+    pub ack_timeout: Duration,
+    /// ACK_RANDOM_FACTOR, the initial timeout is picked randomly between
+    /// ack_timeout and ack_timeout * ack_random_factor
+    pub ack_random_factor: f64,
+    /// MAX_RETRANSMIT, the number of retransmissions after the first transmission
+    pub max_retransmit: usize,
+    /// NSTART, the number of simultaneous outstanding interactions with the peer,
+    /// further requests are queued until one of them completes
+    pub nstart: usize,
+}
+
+impl Default for TransmissionParameters {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(DEFAULT_RECEIVE_TIMEOUT_SECONDS),
+            ack_random_factor: DEFAULT_ACK_RANDOM_FACTOR,
+            max_retransmit: DEFAULT_MAX_RETRANSMIT,
+            nstart: DEFAULT_NSTART,
+        }
+    }
+}
+
+impl TransmissionParameters {
+    /// a random timeout between ack_timeout and ack_timeout * ack_random_factor
+    pub fn initial_timeout(&self) -> Duration {
+        let spread = (self.ack_random_factor - 1.0).max(0.0);
+        self.ack_timeout
+            .mul_f64(1.0 + rand::random::<f64>() * spread)
+    }
+
+    /// the time to wait after each transmission of a confirmable message: a randomized
+    /// initial timeout which is doubled on every retransmission
+    pub fn retransmission_timeouts(&self) -> impl Iterator<Item = Duration> {
+        let initial = self.initial_timeout();
+        (0..=self.max_retransmit)
+            .map(move |n| initial.saturating_mul(2u32.saturating_pow(n as u32)))
+    }
+
+    /// MAX_TRANSMIT_SPAN, the maximum time from the first transmission to the last
+    /// retransmission of a confirmable message
+    pub fn max_transmit_span(&self) -> Duration {
+        let retransmissions = 2u32.saturating_pow(self.max_retransmit as u32) - 1;
+        self.ack_timeout
+            .saturating_mul(retransmissions)
+            .mul_f64(self.ack_random_factor.max(1.0))
+    }
+
+    /// EXCHANGE_LIFETIME, how long a message id of a confirmable message is in use
+    pub fn exchange_lifetime(&self) -> Duration {
+        self.max_transmit_span() + 2 * Duration::from_secs(MAX_LATENCY_SECONDS) + self.ack_timeout
+    }
+
+    /// NON_LIFETIME, how long a message id of a non-confirmable message is in use
+    pub fn non_lifetime(&self) -> Duration {
+        self.max_transmit_span() + Duration::from_secs(MAX_LATENCY_SECONDS)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Packet {
@@ -59,6 +139,30 @@ pub trait ClientTransport: Send + Sync {
     async fn send(&self, buf: &[u8]) -> std::io::Result<usize>;
 }
 
+/// A hook into every request sent with `CoAPClient::send` and `CoAPClient::send_streaming`,
+/// e.g. to add options, log or sign requests. Interceptors run in the order they were added,
+/// their response hooks in the reverse order
+#[async_trait]
+pub trait Interceptor: Send + Sync {
+    /// called before the request is sent. Returning a response skips sending the request and
+    /// the request hooks of the following interceptors
+    async fn on_request(
+        &self,
+        _request: &mut CoapRequest<SocketAddr>,
+    ) -> Result<Option<CoapResponse>, ClientError> {
+        Ok(None)
+    }
+
+    /// called with the response to a request whose request hook ran
+    async fn on_response(
+        &self,
+        _request: &CoapRequest<SocketAddr>,
+        _response: &mut CoapResponse,
+    ) -> Result<(), ClientError> {
+        Ok(())
+    }
+}
+
 trait TransportExt {
     async fn receive_packet(&self) -> IoResult<Option<Packet>>;
 }
@@ -77,18 +181,29 @@ impl<T: ClientTransport> TransportExt for T {
 /// we only use the token as the identifier, and an empty token to represent empty requests
 type Token = Vec<u8>;
 type PacketRegistry = BTreeMap<Token, UnboundedSender<IoResult<Packet>>>;
+/// empty ACKs and resets carry no token, so they are matched by the message id of the request
+type MessageIdRegistry = BTreeMap<u16, Token>;
 
 #[derive(Clone)]
 pub struct TransportSynchronizer {
     pub(crate) outgoing: Arc<Mutex<PacketRegistry>>,
+    message_ids: Arc<Mutex<MessageIdRegistry>>,
+    /// senders which take over their token once the exchanges using it end
+    handovers: Arc<Mutex<PacketRegistry>>,
     fail_error: Arc<RwLock<Option<std::io::Error>>>,
+    /// the transmission parameters of the client, which determine how long the receive
+    /// loop remembers messages for duplicate detection
+    parameters: Arc<watch::Sender<TransmissionParameters>>,
 }
 
 impl TransportSynchronizer {
     pub fn new() -> Self {
         Self {
             outgoing: Arc::new(Mutex::new(PacketRegistry::new())),
+            message_ids: Arc::new(Mutex::new(MessageIdRegistry::new())),
+            handovers: Arc::new(Mutex::new(PacketRegistry::new())),
             fail_error: Arc::new(RwLock::new(None)),
+            parameters: Arc::new(watch::channel(TransmissionParameters::default()).0),
         }
     }
 
@@ -136,8 +251,236 @@ impl TransportSynchronizer {
         self.check_for_error(&sender).await?;
         self.outgoing.lock().await.insert(key, sender)
     }
+
+    /// Sets the sender of a given key like `set_sender`,
+    /// but fails if another exchange with the same key is in flight
+    pub async fn try_set_sender(
+        &self,
+        key: Vec<u8>,
+        sender: UnboundedSender<IoResult<Packet>>,
+    ) -> IoResult<()> {
+        if self.check_for_error(&sender).await.is_none() {
+            // the receiver already got the error of the failed transport
+            return Ok(());
+        }
+        let mut outgoing = self.outgoing.lock().await;
+        let handover = self.handovers.lock().await.get(&key).cloned();
+        match outgoing.entry(key) {
+            // a token reserved by generate_token, or whose receiver is gone
+            Entry::Occupied(mut entry) if entry.get().is_closed() => {
+                entry.insert(sender);
+                Ok(())
+            }
+            // an exchange takes the token over from a handover sender until it ends
+            Entry::Occupied(mut entry)
+                if handover
+                    .as_ref()
+                    .is_some_and(|handover| handover.same_channel(entry.get())) =>
+            {
+                entry.insert(sender);
+                Ok(())
+            }
+            Entry::Occupied(_) => Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "token is already in use",
+            )),
+            Entry::Vacant(entry) => {
+                entry.insert(sender);
+                Ok(())
+            }
+        }
+    }
+
+    /// generates a random token of the given length which is not in use. The token stays
+    /// reserved until a sender is set for it or it is released
+    pub async fn generate_token(&self, length: usize) -> Vec<u8> {
+        let mut outgoing = self.outgoing.lock().await;
+        loop {
+            let token: Vec<u8> = (0..length).map(|_| rand::random::<u8>()).collect();
+            if let Entry::Vacant(entry) = outgoing.entry(token.clone()) {
+                // a sender without a receiver, try_set_sender replaces it
+                entry.insert(unbounded_channel().0);
+                return token;
+            }
+        }
+    }
+
+    /// releases a token reserved by `generate_token` which no exchange used
+    pub async fn release_token(&self, key: &[u8]) {
+        let mut outgoing = self.outgoing.lock().await;
+        if outgoing.get(key).is_some_and(UnboundedSender::is_closed) {
+            outgoing.remove(key);
+        }
+    }
     pub async fn remove_sender(&self, key: &[u8]) -> Option<UnboundedSender<IoResult<Packet>>> {
-        self.outgoing.lock().await.remove(key)
+        let mut outgoing = self.outgoing.lock().await;
+        let removed = outgoing.remove(key);
+        if let Some(handover) = self.handovers.lock().await.get(key) {
+            outgoing.insert(key.to_vec(), handover.clone());
+        }
+        removed
+    }
+
+    /// Routes the messages with the token to the sender whenever no exchange uses it, so
+    /// that nothing is lost between an exchange and a longer lived receiver like an
+    /// observation. Exchanges can still use the token, the sender gets it back once they end
+    pub(crate) async fn start_handover(
+        &self,
+        key: Vec<u8>,
+        sender: UnboundedSender<IoResult<Packet>>,
+    ) -> IoResult<()> {
+        if self.check_for_error(&sender).await.is_none() {
+            return Ok(());
+        }
+        let mut outgoing = self.outgoing.lock().await;
+        if outgoing.get(&key).is_some_and(|sender| !sender.is_closed()) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "token is already in use",
+            ));
+        }
+        self.handovers
+            .lock()
+            .await
+            .insert(key.clone(), sender.clone());
+        outgoing.insert(key, sender);
+        Ok(())
+    }
+
+    /// ends a handover, the sender keeps the token until it is removed
+    pub(crate) async fn finish_handover(&self, key: &[u8]) {
+        self.handovers.lock().await.remove(key);
+    }
+
+    /// the sender a handover routes the messages with the token to
+    pub(crate) async fn handover_sender(
+        &self,
+        key: &[u8],
+    ) -> Option<UnboundedSender<IoResult<Packet>>> {
+        self.handovers.lock().await.get(key).cloned()
+    }
+
+    /// sets the transmission parameters the receive loop uses
+    pub(crate) fn set_parameters(&self, parameters: TransmissionParameters) {
+        self.parameters.send_replace(parameters);
+    }
+
+    /// associates the message id of an outgoing message with its token
+    pub async fn set_message_id(&self, message_id: u16, key: Vec<u8>) {
+        self.message_ids.lock().await.insert(message_id, key);
+    }
+
+    /// removes the association of a message id, if it still belongs to the given token
+    pub async fn remove_message_id(&self, message_id: u16, key: &[u8]) {
+        let mut message_ids = self.message_ids.lock().await;
+        if message_ids.get(&message_id).map(Vec::as_slice) == Some(key) {
+            message_ids.remove(&message_id);
+        }
+    }
+
+    pub async fn get_sender_for_message_id(
+        &self,
+        message_id: u16,
+    ) -> Option<UnboundedSender<IoResult<Packet>>> {
+        let key = self.message_ids.lock().await.get(&message_id).cloned()?;
+        self.get_sender(&key).await
+    }
+}
+
+/// remembers the confirmable and non-confirmable messages received from a peer to detect
+/// duplicates as described in RFC 7252 section 4.5
+struct DuplicateDetector {
+    received: HashMap<(Option<SocketAddr>, u16), ReceivedMessage>,
+    parameters: watch::Receiver<TransmissionParameters>,
+    last_prune: Instant,
+}
+
+struct ReceivedMessage {
+    expires: Instant,
+    /// the ACK or RST we replied with, sent again for every duplicate
+    reply: Option<Vec<u8>>,
+}
+
+impl DuplicateDetector {
+    const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+    fn new(parameters: watch::Receiver<TransmissionParameters>) -> Self {
+        Self {
+            received: HashMap::new(),
+            parameters,
+            last_prune: Instant::now(),
+        }
+    }
+
+    fn key(packet: &Packet) -> Option<(Option<SocketAddr>, u16)> {
+        match packet.message.header.get_type() {
+            MessageType::Confirmable | MessageType::NonConfirmable => {
+                Some((packet.address, packet.message.header.message_id))
+            }
+            _ => None,
+        }
+    }
+
+    /// returns the reply sent for an earlier copy of this message, if it is a duplicate
+    fn duplicate(&self, packet: &Packet) -> Option<Option<Vec<u8>>> {
+        let key = Self::key(packet)?;
+        let now = Instant::now();
+        match self.received.get(&key) {
+            Some(received) if received.expires > now => Some(received.reply.clone()),
+            _ => None,
+        }
+    }
+
+    fn record(&mut self, packet: &Packet, reply: Option<Vec<u8>>) {
+        let Some(key) = Self::key(packet) else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(self.last_prune) > Self::PRUNE_INTERVAL {
+            self.received.retain(|_, received| received.expires > now);
+            self.last_prune = now;
+        }
+        let parameters = *self.parameters.borrow();
+        let lifetime = match packet.message.header.get_type() {
+            MessageType::Confirmable => parameters.exchange_lifetime(),
+            _ => parameters.non_lifetime(),
+        };
+        self.received.insert(
+            key,
+            ReceivedMessage {
+                expires: now + lifetime,
+                reply,
+            },
+        );
+    }
+}
+
+/// orders the notifications of an observation by their sequence numbers as described in
+/// RFC 7641 section 3.4
+#[derive(Debug, Default)]
+struct ObserveFreshness {
+    last: Option<(u32, Instant)>,
+}
+
+impl ObserveFreshness {
+    const WRAP_THRESHOLD: u32 = 1 << 23;
+    const MAX_REORDER_TIME: Duration = Duration::from_secs(128);
+
+    /// whether a notification with this sequence number, received at `now`, is newer than
+    /// the last fresh one. Fresh notifications become the new reference
+    fn is_fresh(&mut self, sequence: u32, now: Instant) -> bool {
+        let fresh = match self.last {
+            None => true,
+            Some((last_sequence, last_time)) => {
+                (last_sequence < sequence && sequence - last_sequence < Self::WRAP_THRESHOLD)
+                    || (last_sequence > sequence && last_sequence - sequence > Self::WRAP_THRESHOLD)
+                    || now > last_time + Self::MAX_REORDER_TIME
+            }
+        };
+        if fresh {
+            self.last = Some((sequence, now));
+        }
+        fresh
     }
 }
 
@@ -145,6 +488,7 @@ async fn receive_loop<T: ClientTransport + 'static>(
     transport: Weak<T>,
     transport_sync: TransportSynchronizer,
 ) -> std::io::Result<()> {
+    let mut duplicates = DuplicateDetector::new(transport_sync.parameters.subscribe());
     let err = loop {
         let Some(transport_instance) = transport.upgrade() else {
             // nobody else is listening so we can drop our reference
@@ -167,27 +511,62 @@ async fn receive_loop<T: ClientTransport + 'static>(
             trace!("unexpected malformed packet received");
             continue;
         };
-        if let Some(ack) = parse_for_ack(&packet) {
-            transport_instance.send(&ack).await?;
-        }
-
-        let MessageClass::Response(_) = packet.message.header.code else {
+        if let Some(reply) = duplicates.duplicate(&packet) {
+            debug!("duplicate message {}", packet.message.header.message_id);
+            if let Some(reply) = reply {
+                transport_instance.send(&reply).await?;
+            }
             continue;
+        }
+        let sender = match (packet.message.header.get_type(), packet.message.header.code) {
+            // an empty ACK announces a separate response and a RST rejects a message,
+            // both are matched by message id
+            (MessageType::Acknowledgement, MessageClass::Empty) | (MessageType::Reset, _) => {
+                let message_id = packet.message.header.message_id;
+                let sender = transport_sync.get_sender_for_message_id(message_id).await;
+                if sender.is_none() {
+                    info!(
+                        "received unexpected ack or reset for message id {}",
+                        message_id
+                    );
+                }
+                sender
+            }
+            (_, MessageClass::Response(_)) => {
+                let token = packet.message.get_token();
+                let sender = transport_sync
+                    .get_sender(token)
+                    .await
+                    .filter(|sender| !sender.is_closed());
+                if sender.is_none() {
+                    info!("received unexpected response for token {:?}", &token);
+                }
+                sender
+            }
+            (_, m) => {
+                debug!("unknown message type {}", m);
+                None
+            }
+        };
+        // responses nobody waits for are rejected, so that servers stop sending
+        // notifications for forgotten observations, RFC 7641 section 3.6
+        let reply = match sender {
+            Some(_) => parse_for_ack(&packet),
+            None => parse_for_reset(&packet),
         };
+        if let Some(reply) = &reply {
+            transport_instance.send(reply).await?;
+        }
+        duplicates.record(&packet, reply);
 
-        let token = packet.message.get_token();
-        let Some(sender) = transport_sync.get_sender(token).await else {
-            info!("received unexpected response for token {:?}", &token);
+        let Some(sender) = sender else {
             continue;
         };
-        match packet.message.header.code {
-            MessageClass::Response(_) => {}
-            m => {
-                debug!("unknown message type {}", m);
-                continue;
-            }
+        let message = match packet.message.header.get_type() {
+            MessageType::Reset => Err(Error::new(ErrorKind::ConnectionReset, "reset by peer")),
+            _ => Ok(packet),
         };
-        let Ok(_) = sender.send(Ok(packet)) else {
+        let Ok(_) = sender.send(message) else {
             debug!("unexpected drop of sender");
             continue;
         };
@@ -205,6 +584,59 @@ pub fn parse_for_ack(packet: &Packet) -> Option<Vec<u8>> {
     }
 }
 
+/// the RST rejecting an unexpected confirmable or non-confirmable response
+fn parse_for_reset(packet: &Packet) -> Option<Vec<u8>> {
+    match (packet.message.header.get_type(), packet.message.header.code) {
+        (MessageType::Confirmable | MessageType::NonConfirmable, MessageClass::Response(_)) => {
+            Some(make_reset(packet))
+        }
+        _ => None,
+    }
+}
+
+fn make_reset(packet: &Packet) -> Vec<u8> {
+    let mut reset = Message::new();
+    reset.header.set_type(MessageType::Reset);
+    reset.header.message_id = packet.message.header.message_id;
+    reset.header.code = MessageClass::Empty;
+    reset.to_bytes().unwrap()
+}
+
+/// encodes the value of an unsigned integer option in as few bytes as possible
+pub(crate) fn encode_uint(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let leading_zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    bytes[leading_zeros..].to_vec()
+}
+
+/// decodes the value of an unsigned integer option
+pub(crate) fn decode_uint(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | u64::from(*byte))
+}
+
+/// the Max-Age of a message, 60 seconds if it has none. The option has at most 4 bytes
+/// (RFC 7252 section 5.10), longer values are clamped to the largest valid one
+pub(crate) fn max_age(message: &Message) -> Duration {
+    let seconds = match message.get_first_option(CoapOption::MaxAge) {
+        None => DEFAULT_MAX_AGE_SECONDS,
+        Some(max_age) if max_age.len() > 4 => u64::from(u32::MAX),
+        Some(max_age) => decode_uint(max_age),
+    };
+    Duration::from_secs(seconds)
+}
+
+/// whether the message is a 2.xx response
+pub(crate) fn is_success(message: &Message) -> bool {
+    matches!(message.header.code, code @ MessageClass::Response(_) if u8::from(code) >> 5 == 2)
+}
+
+fn is_empty_ack(packet: &Packet) -> bool {
+    packet.message.header.get_type() == MessageType::Acknowledgement
+        && packet.message.header.code == MessageClass::Empty
+}
+
 pub fn make_ack(packet: &Packet) -> Vec<u8> {
     let mut ack = Message::new();
     ack.header.set_type(MessageType::Acknowledgement);
@@ -217,8 +649,12 @@ pub fn make_ack(packet: &Packet) -> Vec<u8> {
 struct CoapClientTransport<T: ClientTransport> {
     pub(crate) transport: Arc<T>,
     pub(crate) synchronizer: TransportSynchronizer,
-    pub(crate) retries: usize,
-    pub(crate) timeout: Duration,
+    pub(crate) parameters: TransmissionParameters,
+    /// how long to wait for a separate response after an empty ACK,
+    /// EXCHANGE_LIFETIME is used if not set
+    pub(crate) separate_response_timeout: Option<Duration>,
+    /// permits for outstanding interactions, shared by all clones of a client
+    pub(crate) outstanding: Arc<Semaphore>,
 }
 
 impl<T: ClientTransport> Clone for CoapClientTransport<T> {
@@ -226,52 +662,103 @@ impl<T: ClientTransport> Clone for CoapClientTransport<T> {
         Self {
             transport: self.transport.clone(),
             synchronizer: self.synchronizer.clone(),
-            retries: self.retries.clone(),
-            timeout: self.timeout.clone(),
+            parameters: self.parameters,
+            separate_response_timeout: self.separate_response_timeout,
+            outstanding: self.outstanding.clone(),
         }
     }
 }
 
 impl<T: ClientTransport> CoapClientTransport<T> {
-    pub const DEFAULT_NUM_RETRIES: usize = 5;
-    async fn establish_receiver_for(&self, packet: &Packet) -> UnboundedReceiver<IoResult<Packet>> {
+    async fn establish_receiver_for(
+        &self,
+        packet: &Packet,
+    ) -> IoResult<UnboundedReceiver<IoResult<Packet>>> {
         let (tx, rx) = unbounded_channel();
         let token = packet.message.get_token().to_owned();
-        self.synchronizer.set_sender(token, tx).await;
-        return rx;
+        self.synchronizer.try_set_sender(token.clone(), tx).await?;
+        self.synchronizer
+            .set_message_id(packet.message.header.message_id, token)
+            .await;
+        return Ok(rx);
     }
 
-    /// tries to send a confirmable message with retries and timeouts
+    /// tries to send a confirmable message with retransmissions using an exponential back-off
+    /// as described in RFC 7252 section 4.2
     async fn try_send_confirmable_message(
         &self,
         msg: &Packet,
         receiver: &mut UnboundedReceiver<IoResult<Packet>>,
     ) -> IoResult<Packet> {
-        let mut res = Err(Error::new(ErrorKind::InvalidData, "not enough retries"));
-        for _ in 0..self.retries {
-            res = self.try_send_non_confirmable_message(&msg, receiver).await;
-            if res.is_ok() {
-                return res;
+        let mut res = Err(Error::new(ErrorKind::TimedOut, "not enough retries"));
+        for wait in self.parameters.retransmission_timeouts() {
+            res = self.try_send_message(&msg, receiver, wait).await;
+            match res {
+                // an empty ACK means the peer will send the response later, either way
+                // retransmitting stops
+                Ok(_) => return res,
+                // retransmitting a rejected message is pointless
+                Err(ref e) if e.kind() == ErrorKind::ConnectionReset => return res,
+                Err(_) => {}
             }
         }
         return res;
     }
 
+    /// waits for the response following an empty ACK, RFC 7252 section 5.2.2
+    async fn wait_for_separate_response(
+        &self,
+        receiver: &mut UnboundedReceiver<IoResult<Packet>>,
+    ) -> IoResult<Packet> {
+        let wait = self
+            .separate_response_timeout
+            .unwrap_or_else(|| self.parameters.exchange_lifetime());
+        let deadline = Instant::now() + wait;
+        loop {
+            match timeout_at(deadline, receiver.recv()).await {
+                // a duplicated empty ACK
+                Ok(Some(Ok(packet))) if is_empty_ack(&packet) => continue,
+                Ok(Some(res)) => return res,
+                Ok(None) | Err(_) => {
+                    return Err(Error::new(ErrorKind::TimedOut, "separate response timeout"))
+                }
+            }
+        }
+    }
+
     fn encode_message(message: &Message) -> IoResult<Vec<u8>> {
         message
             .to_bytes()
             .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e.to_string()))
     }
 
+    /// sends a message without waiting for a reply, replies are routed by the synchronizer
+    async fn send_message(&self, message: &Message) -> IoResult<()> {
+        let bytes = Self::encode_message(message)?;
+        self.transport.send(&bytes).await?;
+        Ok(())
+    }
+
     async fn try_send_non_confirmable_message(
         &self,
         msg: &Packet,
         receiver: &mut UnboundedReceiver<IoResult<Packet>>,
+    ) -> IoResult<Packet> {
+        self.try_send_message(msg, receiver, self.parameters.ack_timeout)
+            .await
+    }
+
+    /// sends a message once and waits for the given time for a response
+    async fn try_send_message(
+        &self,
+        msg: &Packet,
+        receiver: &mut UnboundedReceiver<IoResult<Packet>>,
+        wait: Duration,
     ) -> IoResult<Packet> {
         let bytes = Self::encode_message(&msg.message)?;
         self.transport.send(&bytes).await?;
         let try_receive: Result<Option<Result<Packet, Error>>, tokio::time::error::Elapsed> =
-            timeout(self.timeout, receiver.recv()).await;
+            timeout(wait, receiver.recv()).await;
         if let Ok(Some(res)) = try_receive {
             return res;
         }
@@ -293,11 +780,35 @@ impl<T: ClientTransport> CoapClientTransport<T> {
     }
 
     pub async fn do_request_response_for_packet(&self, packet: &Packet) -> IoResult<Packet> {
-        let mut receiver = self.establish_receiver_for(packet).await;
-        let result = self
+        // limit the outstanding interactions with the peer, RFC 7252 section 4.7
+        let permit = self
+            .outstanding
+            .acquire()
+            .await
+            .map_err(|_| Error::new(ErrorKind::Other, "outstanding requests closed"))?;
+        let mut receiver = self.establish_receiver_for(packet).await?;
+        let mut result = self
             .do_request_response_for_packet_inner(packet, &mut receiver)
             .await;
-        self.synchronizer.remove_sender(packet.message.get_token()).await;
+        // the interaction is no longer outstanding once the peer acknowledged it, so the
+        // separate response is awaited without holding the permit
+        drop(permit);
+        if matches!(result, Ok(ref packet) if is_empty_ack(packet)) {
+            result = self.wait_for_separate_response(&mut receiver).await;
+        }
+        let token = packet.message.get_token();
+        self.synchronizer.remove_sender(token).await;
+        // messages routed to the exchange after it took its response, like a notification
+        // right behind the registration response, are passed on to the handover sender.
+        // The channel closes once the receive loop dropped its copy of the sender
+        if let Some(handover) = self.synchronizer.handover_sender(token).await {
+            while let Some(message) = receiver.recv().await {
+                let _ = handover.send(message);
+            }
+        }
+        self.synchronizer
+            .remove_message_id(packet.message.header.message_id, token)
+            .await;
         result
     }
 
@@ -305,8 +816,9 @@ impl<T: ClientTransport> CoapClientTransport<T> {
         return Self {
             transport,
             synchronizer,
-            retries: Self::DEFAULT_NUM_RETRIES,
-            timeout: Duration::from_secs(DEFAULT_RECEIVE_TIMEOUT_SECONDS),
+            parameters: TransmissionParameters::default(),
+            separate_response_timeout: None,
+            outstanding: Arc::new(Semaphore::new(DEFAULT_NSTART)),
         };
     }
 }
@@ -334,7 +846,11 @@ pub type UdpCoAPClient = CoAPClient<UdpTransport>;
 pub struct CoAPClient<T: ClientTransport> {
     transport: CoapClientTransport<T>,
     block1_size: usize,
+    block2_size: Option<usize>,
+    max_response_size: usize,
     message_id: Arc<AtomicU16>,
+    token_length: usize,
+    interceptors: Vec<Arc<dyn Interceptor>>,
 }
 
 impl<T: ClientTransport> Clone for CoAPClient<T> {
@@ -342,7 +858,11 @@ impl<T: ClientTransport> Clone for CoAPClient<T> {
         Self {
             transport: self.transport.clone(),
             block1_size: self.block1_size.clone(),
+            block2_size: self.block2_size,
+            max_response_size: self.max_response_size,
             message_id: self.message_id.clone(),
+            token_length: self.token_length,
+            interceptors: self.interceptors.clone(),
         }
     }
 }
@@ -386,6 +906,187 @@ impl Drop for MessageReceiver {
     }
 }
 
+/// The errors of the client
+#[derive(Debug)]
+pub enum ClientError {
+    /// no response arrived in time
+    Timeout,
+    /// the peer rejected the message with a RST
+    Reset,
+    /// the response could not be parsed or does not belong to the request
+    MalformedResponse(String),
+    /// the blockwise transfer could not be completed
+    BlockNegotiation(String),
+    /// the server answered with a status the operation cannot continue with
+    ResponseStatus { status: Status, payload: Vec<u8> },
+    /// a blockwise response grew larger than the maximum response size of the client
+    ResponseTooLarge { max_size: usize },
+    /// the url could not be parsed
+    Url(String),
+    /// a coaps url was requested without a DTLS config or without the dtls feature
+    DtlsNotConfigured,
+    /// the forward proxy does not proxy to the target, 5.05
+    ProxyingNotSupported,
+    /// the forward proxy could not reach the target, 5.02
+    BadGateway,
+    /// the request payload could not be encoded or the response payload decoded
+    Payload(PayloadError),
+    /// the underlying transport failed
+    Transport(Error),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Timeout => write!(f, "timed out"),
+            ClientError::Reset => write!(f, "reset by peer"),
+            ClientError::MalformedResponse(reason) => write!(f, "malformed response: {}", reason),
+            ClientError::BlockNegotiation(reason) => {
+                write!(f, "blockwise transfer failed: {}", reason)
+            }
+            ClientError::ResponseStatus { status, .. } => {
+                write!(f, "unexpected response status {:?}", status)
+            }
+            ClientError::ResponseTooLarge { max_size } => {
+                write!(f, "response exceeds the maximum size of {} bytes", max_size)
+            }
+            ClientError::Url(reason) => write!(f, "invalid url: {}", reason),
+            ClientError::DtlsNotConfigured => write!(f, "coaps urls need a DTLS config"),
+            ClientError::ProxyingNotSupported => write!(f, "proxying not supported"),
+            ClientError::BadGateway => write!(f, "the proxy could not reach the target"),
+            ClientError::Payload(e) => write!(f, "invalid payload: {}", e),
+            ClientError::Transport(e) => write!(f, "transport error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Transport(e) => Some(e),
+            ClientError::Payload(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for ClientError {
+    fn from(e: Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<ClientError>()) {
+            return *e.into_inner().unwrap().downcast::<ClientError>().unwrap();
+        }
+        match e.kind() {
+            ErrorKind::TimedOut => ClientError::Timeout,
+            ErrorKind::ConnectionReset => ClientError::Reset,
+            _ => ClientError::Transport(e),
+        }
+    }
+}
+
+impl From<ClientError> for Error {
+    fn from(e: ClientError) -> Self {
+        let kind = match e {
+            ClientError::Transport(e) => return e,
+            ClientError::Timeout => ErrorKind::TimedOut,
+            ClientError::Reset => ErrorKind::ConnectionReset,
+            ClientError::MalformedResponse(_)
+            | ClientError::BlockNegotiation(_)
+            | ClientError::ResponseTooLarge { .. }
+            | ClientError::Payload(_) => ErrorKind::InvalidData,
+            ClientError::ResponseStatus { .. } => ErrorKind::Other,
+            ClientError::Url(_) | ClientError::DtlsNotConfigured => ErrorKind::InvalidInput,
+            ClientError::ProxyingNotSupported => ErrorKind::Unsupported,
+            ClientError::BadGateway => ErrorKind::Other,
+        };
+        Error::new(kind, e)
+    }
+}
+
+impl ClientError {
+    /// the error for a response with an unexpected status
+    pub(crate) fn response_status(message: &Message) -> Self {
+        match message.header.code {
+            MessageClass::Response(status) => ClientError::ResponseStatus {
+                status,
+                payload: message.payload.clone(),
+            },
+            code => ClientError::MalformedResponse(format!("unexpected code {}", code)),
+        }
+    }
+}
+
+/// The body of a response as a stream of chunks, one per Block2 block
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>, ClientError>> + Send>>;
+
+/// The responses to a multicast request with the address of each responder
+pub type MulticastStream =
+    Pin<Box<dyn Stream<Item = Result<(SocketAddr, CoapResponse), ClientError>> + Send>>;
+
+/// the progress of a streamed blockwise download
+struct BodyStreamState<T: ClientTransport> {
+    client: CoAPClient<T>,
+    request: CoapRequest<SocketAddr>,
+    first_chunk: Option<Vec<u8>>,
+    next_block: Option<BlockValue>,
+    etag: Option<Vec<u8>>,
+}
+
+/// A notification of an observed resource
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub message: Message,
+}
+
+impl Notification {
+    /// the sequence number from the Observe option
+    pub fn sequence(&self) -> Option<u32> {
+        self.message.get_observe_value().and_then(|x| x.ok())
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.message.payload
+    }
+}
+
+/// The notifications of an observation, created with `CoAPClient::observe_stream`. Errors end
+/// the stream, dropping it deregisters the observation
+pub struct ObserveStream {
+    notifications: UnboundedReceiver<Result<Notification, ClientError>>,
+    terminate: Option<oneshot::Sender<ObserveMessage>>,
+    finished: bool,
+}
+
+impl Stream for ObserveStream {
+    type Item = Result<Notification, ClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        let item = futures::ready!(self.notifications.poll_recv(cx));
+        if !matches!(item, Some(Ok(_))) {
+            // the observation is over, it is cancelled now rather than when the stream drops
+            self.finished = true;
+            self.terminate();
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl ObserveStream {
+    fn terminate(&mut self) {
+        if let Some(terminate) = self.terminate.take() {
+            let _ = terminate.send(ObserveMessage::Terminate);
+        }
+    }
+}
+
+impl Drop for ObserveStream {
+    fn drop(&mut self) {
+        self.terminate();
+    }
+}
+
 impl UdpCoAPClient {
     pub async fn new_with_specific_source<A: ToSocketAddrs, B: ToSocketAddrs>(
         bind_addr: A,
@@ -455,8 +1156,14 @@ impl UdpCoAPClient {
         request: &CoapRequest<SocketAddr>,
         segment: u8,
     ) -> IoResult<()> {
+        self.send_multicast(request, &self.all_coap_addr(segment))
+            .await
+    }
+
+    /// the All-CoAP multicast address in the family and with the port of the peer address
+    fn all_coap_addr(&self, segment: u8) -> SocketAddr {
         assert!(segment <= 0xf);
-        let addr = match self.transport.transport.peer_addr {
+        match self.transport.transport.peer_addr {
             SocketAddr::V4(val) => {
                 SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 1, 187)), val.port())
             }
@@ -473,9 +1180,7 @@ impl UdpCoAPClient {
                 )),
                 val.port(),
             ),
-        };
-
-        self.send_multicast(request, &addr).await
+        }
     }
 
     /// Send a multicast request to multiple devices.
@@ -547,22 +1252,333 @@ impl UdpCoAPClient {
             request.message.get_token(),
         );
     }
-}
 
-#[cfg(feature = "dtls")]
-impl CoAPClient<DtlsConnection> {
-    pub async fn from_udp_dtls_config(config: UdpDtlsConfig) -> IoResult<Self> {
-        Ok(CoAPClient::from_transport(
-            DtlsConnection::try_new(config).await?,
-        ))
+    /// like `create_receiver_for`, but fails if an exchange with the token of the request is
+    /// in flight instead of taking over its responses
+    pub async fn try_create_receiver_for(
+        &self,
+        request: &CoapRequest<SocketAddr>,
+    ) -> IoResult<MessageReceiver> {
+        let (tx, rx) = unbounded_channel();
+        let key = request.message.get_token().to_vec();
+        self.transport.synchronizer.try_set_sender(key, tx).await?;
+        return Ok(MessageReceiver::new(
+            self.transport.synchronizer.clone(),
+            rx,
+            request.message.get_token(),
+        ));
     }
-}
 
-impl<T: ClientTransport + 'static> CoAPClient<T> {
-    const MAX_PAYLOAD_BLOCK: usize = 1024;
-    /// Create a CoAP client with a chosen transport type
+    /// Send a request to the peer address of the client, usually a multicast group like
+    /// '224.0.1.187:5683', and collect the responses arriving within `window`, one per
+    /// responder. Servers delay their responses by a random leisure (RFC 7252 section 8.2),
+    /// so the window should be longer than the leisure of the group.
+    /// The request is always sent non-confirmable with a new random token
+    pub async fn multicast_collect(
+        &self,
+        request: CoapRequest<SocketAddr>,
+        window: Duration,
+    ) -> Result<Vec<(SocketAddr, CoapResponse)>, ClientError> {
+        self.multicast_stream(request, window)
+            .await?
+            .try_collect()
+            .await
+    }
 
-    pub fn from_transport(transport: T) -> Self {
+    /// Like `multicast_collect`, but yields the responses as they arrive. The stream ends
+    /// when the window is over
+    pub async fn multicast_stream(
+        &self,
+        request: CoapRequest<SocketAddr>,
+        window: Duration,
+    ) -> Result<MulticastStream, ClientError> {
+        let peer_addr = self.transport.transport.peer_addr;
+        self.multicast_stream_to(request, peer_addr, window).await
+    }
+
+    async fn multicast_stream_to(
+        &self,
+        mut request: CoapRequest<SocketAddr>,
+        addr: SocketAddr,
+        window: Duration,
+    ) -> Result<MulticastStream, ClientError> {
+        // RFC 7252 section 8.1, multicast requests must not be confirmable
+        request.message.header.set_type(MessageType::NonConfirmable);
+        request.message.header.message_id = self.gen_message_id();
+        let token = self
+            .transport
+            .synchronizer
+            .generate_token(self.token_length)
+            .await;
+        request.message.set_token(token);
+        let receiver = self.try_create_receiver_for(&request).await?;
+        self.send_multicast(&request, &addr).await?;
+
+        let deadline = Instant::now() + window;
+        let state = Some((receiver, HashSet::new()));
+        Ok(Box::pin(stream::unfold(state, move |state| async move {
+            let (mut receiver, mut responders) = state?;
+            loop {
+                let packet = match timeout_at(deadline, receiver.receive()).await {
+                    Err(_) => return None,
+                    Ok(Err(e)) => return Some((Err(ClientError::from(e)), None)),
+                    Ok(Ok(packet)) => packet,
+                };
+                let Some(address) = packet.address else {
+                    continue;
+                };
+                // a server may answer more than once, only its first response counts
+                if !responders.insert(address) {
+                    debug!("ignoring another response from {}", address);
+                    continue;
+                }
+                let response = CoapResponse {
+                    message: packet.message,
+                };
+                return Some((Ok((address, response)), Some((receiver, responders))));
+            }
+        })))
+    }
+
+    /// Discover the resources of all CoAP devices in a segment (see `send_all_coap`) which
+    /// answer within `window`. Responses which are not link-format documents are skipped.
+    /// Multicast responses are not fetched blockwise, use `discover` on a client for the
+    /// device to get the rest of a large document
+    pub async fn discover_all_coap(
+        &self,
+        segment: u8,
+        query: Option<&str>,
+        window: Duration,
+    ) -> Result<Vec<(SocketAddr, Vec<Link>)>, ClientError> {
+        let query = Self::discovery_filter(query);
+        let request = Self::discovery_request(query);
+        let responses: Vec<_> = self
+            .multicast_stream_to(request, self.all_coap_addr(segment), window)
+            .await?
+            .try_collect()
+            .await?;
+        let mut devices = vec![];
+        for (address, response) in responses {
+            match Self::parse_discovery(response, query) {
+                Ok(links) => devices.push((address, links)),
+                Err(e) => debug!("ignoring discovery response from {}: {}", address, e),
+            }
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(feature = "dtls")]
+impl CoAPClient<DtlsConnection> {
+    pub async fn from_udp_dtls_config(config: UdpDtlsConfig) -> IoResult<Self> {
+        Ok(CoAPClient::from_transport(
+            DtlsConnection::try_new(config).await?,
+        ))
+    }
+
+    /// Connect to a server with the DTLS config of the client config
+    pub async fn connect_dtls(
+        host: &str,
+        port: u16,
+        config: &ClientConfig,
+    ) -> Result<Self, ClientError> {
+        let dtls_config = config
+            .dtls_config
+            .clone()
+            .ok_or(ClientError::DtlsNotConfigured)?;
+        let dest_addr = lookup_host((host, port)).await?.next().ok_or(Error::new(
+            ErrorKind::InvalidInput,
+            "could not get socket address",
+        ))?;
+        Ok(Self::from_udp_dtls_config(UdpDtlsConfig {
+            config: dtls_config,
+            dest_addr,
+        })
+        .await?)
+    }
+}
+
+/// The transports of coap urls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    /// coap:// over udp
+    Coap,
+    /// coaps:// over DTLS
+    Coaps,
+}
+
+impl Scheme {
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Scheme::Coap => 5683,
+            Scheme::Coaps => 5684,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Coap => "coap",
+            Scheme::Coaps => "coaps",
+        }
+    }
+}
+
+/// A forward proxy for the requests to coap urls, RFC 7252 section 5.7.2
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// the coap or coaps url of the proxy, e.g. coap://proxy.local
+    pub url: String,
+    /// send the target url in a single Proxy-Uri option instead of a Proxy-Scheme option with
+    /// the Uri-Host, Uri-Port, Uri-Path and Uri-Query options
+    pub proxy_uri: bool,
+}
+
+/// Settings for the requests to coap urls
+#[derive(Clone, Default)]
+pub struct ClientConfig {
+    /// the receive timeout of the client, see `CoAPClient::set_receive_timeout`
+    pub receive_timeout: Option<Duration>,
+    /// the DTLS settings, PSK or certificates, for coaps urls
+    #[cfg(feature = "dtls")]
+    pub dtls_config: Option<webrtc_dtls::config::Config>,
+    /// the forward proxy all requests are sent to
+    pub proxy: Option<ProxyConfig>,
+}
+
+impl ClientConfig {
+    /// the server to send the request for the url to and the request itself, which asks a
+    /// proxy to forward it if one is configured
+    pub(crate) fn route(
+        &self,
+        url: &str,
+        method: Method,
+        data: Option<Vec<u8>>,
+    ) -> Result<(CoapUrl, CoapRequest<SocketAddr>), ClientError> {
+        let target = UdpCoAPClient::parse_coap_url(url)?;
+        let Some(proxy) = &self.proxy else {
+            let request = target.request(method, data);
+            return Ok((target, request));
+        };
+        let server = UdpCoAPClient::parse_coap_url(&proxy.url)?;
+        let request = match proxy.proxy_uri {
+            true => {
+                let mut request = RequestBuilder::new("", method)
+                    .options(vec![(CoapOption::ProxyUri, url.as_bytes().to_vec())])
+                    .data(data)
+                    .build();
+                // Proxy-Uri must not be combined with Uri-* options
+                request.message.clear_option(CoapOption::UriPath);
+                request
+            }
+            false => RequestBuilder::new(&target.path, method)
+                .queries(target.queries.clone())
+                .domain(target.host.clone())
+                .options(vec![
+                    (
+                        CoapOption::ProxyScheme,
+                        target.scheme.as_str().as_bytes().to_vec(),
+                    ),
+                    (CoapOption::UriPort, encode_uint(u64::from(target.port))),
+                ])
+                .data(data)
+                .build(),
+        };
+        Ok((server, request))
+    }
+
+    /// turns the proxy errors of a response into errors
+    pub(crate) fn check_response(
+        &self,
+        response: CoapResponse,
+    ) -> Result<CoapResponse, ClientError> {
+        if self.proxy.is_none() {
+            return Ok(response);
+        }
+        match response.get_status() {
+            Status::ProxyingNotSupported => Err(ClientError::ProxyingNotSupported),
+            Status::BadGateway => Err(ClientError::BadGateway),
+            _ => Ok(response),
+        }
+    }
+}
+
+/// the parts of a coap url
+pub(crate) struct CoapUrl {
+    pub(crate) scheme: Scheme,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) path: String,
+    pub(crate) queries: Option<Vec<u8>>,
+}
+
+impl CoapUrl {
+    pub(crate) fn request(&self, method: Method, data: Option<Vec<u8>>) -> CoapRequest<SocketAddr> {
+        RequestBuilder::new(&self.path, method)
+            .queries(self.queries.clone())
+            .domain(self.host.clone())
+            .data(data)
+            .build()
+    }
+}
+
+/// a client connected with the transport of a url scheme
+#[derive(Clone)]
+pub(crate) enum SchemeClient {
+    Udp(UdpCoAPClient),
+    #[cfg(feature = "dtls")]
+    Dtls(CoAPClient<DtlsConnection>),
+}
+
+impl SchemeClient {
+    pub(crate) async fn connect(url: &CoapUrl, config: &ClientConfig) -> Result<Self, ClientError> {
+        match url.scheme {
+            Scheme::Coap => Ok(SchemeClient::Udp(
+                UdpCoAPClient::new_udp((url.host.as_str(), url.port)).await?,
+            )),
+            #[cfg(feature = "dtls")]
+            Scheme::Coaps => Ok(SchemeClient::Dtls(
+                CoAPClient::connect_dtls(&url.host, url.port, config).await?,
+            )),
+            #[cfg(not(feature = "dtls"))]
+            Scheme::Coaps => {
+                let _ = config;
+                Err(ClientError::DtlsNotConfigured)
+            }
+        }
+    }
+
+    pub(crate) async fn send(
+        &self,
+        request: CoapRequest<SocketAddr>,
+        receive_timeout: Option<Duration>,
+    ) -> Result<CoapResponse, ClientError> {
+        match self {
+            SchemeClient::Udp(client) => Self::send_with(client, request, receive_timeout).await,
+            #[cfg(feature = "dtls")]
+            SchemeClient::Dtls(client) => Self::send_with(client, request, receive_timeout).await,
+        }
+    }
+
+    async fn send_with<T: ClientTransport + 'static>(
+        client: &CoAPClient<T>,
+        request: CoapRequest<SocketAddr>,
+        receive_timeout: Option<Duration>,
+    ) -> Result<CoapResponse, ClientError> {
+        let mut client = client.clone();
+        if let Some(receive_timeout) = receive_timeout {
+            client.set_receive_timeout(receive_timeout);
+        }
+        client.send(request).await
+    }
+}
+
+impl<T: ClientTransport + 'static> CoAPClient<T> {
+    const MAX_PAYLOAD_BLOCK: usize = 1024;
+    const DEFAULT_TOKEN_LENGTH: usize = 4;
+    const DEFAULT_MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+    /// Create a CoAP client with a chosen transport type
+
+    pub fn from_transport(transport: T) -> Self {
         let synchronizer = TransportSynchronizer::new();
         let transport_arc = Arc::new(transport);
         let message_id: u16 = rand::random();
@@ -574,21 +1590,28 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
         CoAPClient {
             transport: CoapClientTransport::from_transport(transport_arc.clone(), synchronizer),
             block1_size: Self::MAX_PAYLOAD_BLOCK,
+            block2_size: None,
+            max_response_size: Self::DEFAULT_MAX_RESPONSE_SIZE,
             message_id: Arc::new(AtomicU16::new(message_id)),
+            token_length: Self::DEFAULT_TOKEN_LENGTH,
+            interceptors: Vec::new(),
         }
     }
     /// Execute a single get request with a coap url
-    pub async fn get(url: &str) -> IoResult<CoapResponse> {
+    pub async fn get(url: &str) -> Result<CoapResponse, ClientError> {
         Self::request(url, Method::Get, None).await
     }
 
     /// Execute a single get request with a coap url and a specific timeout.
-    pub async fn get_with_timeout(url: &str, timeout: Duration) -> IoResult<CoapResponse> {
+    pub async fn get_with_timeout(
+        url: &str,
+        timeout: Duration,
+    ) -> Result<CoapResponse, ClientError> {
         Self::request_with_timeout(url, Method::Get, None, timeout).await
     }
 
     /// Execute a single post request with a coap url using udp
-    pub async fn post(url: &str, data: Vec<u8>) -> IoResult<CoapResponse> {
+    pub async fn post(url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
         Self::request(url, Method::Post, Some(data)).await
     }
 
@@ -597,12 +1620,12 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
         url: &str,
         data: Vec<u8>,
         timeout: Duration,
-    ) -> IoResult<CoapResponse> {
+    ) -> Result<CoapResponse, ClientError> {
         Self::request_with_timeout(url, Method::Post, Some(data), timeout).await
     }
 
     /// Execute a put request with a coap url using udp
-    pub async fn put(url: &str, data: Vec<u8>) -> IoResult<CoapResponse> {
+    pub async fn put(url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
         Self::request(url, Method::Put, Some(data)).await
     }
 
@@ -611,70 +1634,255 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
         url: &str,
         data: Vec<u8>,
         timeout: Duration,
-    ) -> IoResult<CoapResponse> {
+    ) -> Result<CoapResponse, ClientError> {
         Self::request_with_timeout(url, Method::Put, Some(data), timeout).await
     }
 
     /// Execute a single delete request with a coap url using udp
-    pub async fn delete(url: &str) -> IoResult<CoapResponse> {
+    pub async fn delete(url: &str) -> Result<CoapResponse, ClientError> {
         Self::request(url, Method::Delete, None).await
     }
 
     /// Execute a single delete request with a coap url using udp
-    pub async fn delete_with_timeout(url: &str, timeout: Duration) -> IoResult<CoapResponse> {
+    pub async fn delete_with_timeout(
+        url: &str,
+        timeout: Duration,
+    ) -> Result<CoapResponse, ClientError> {
         Self::request_with_timeout(url, Method::Delete, None, timeout).await
     }
 
-    /// Execute a single request (GET, POST, PUT, DELETE) with a coap url using udp
+    /// Execute a fetch request with a coap url using udp, the body describes the part of the
+    /// resource to return (RFC 8132)
+    pub async fn fetch(url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        Self::request(url, Method::Fetch, Some(data)).await
+    }
+
+    /// Execute a single fetch request with a coap url using udp
+    pub async fn fetch_with_timeout(
+        url: &str,
+        data: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<CoapResponse, ClientError> {
+        Self::request_with_timeout(url, Method::Fetch, Some(data), timeout).await
+    }
+
+    /// Execute a patch request with a coap url using udp, the body is a set of changes to
+    /// apply to the resource (RFC 8132)
+    pub async fn patch(url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        Self::request(url, Method::Patch, Some(data)).await
+    }
+
+    /// Execute a single patch request with a coap url using udp
+    pub async fn patch_with_timeout(
+        url: &str,
+        data: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<CoapResponse, ClientError> {
+        Self::request_with_timeout(url, Method::Patch, Some(data), timeout).await
+    }
+
+    /// Execute an ipatch request with a coap url using udp. Unlike patch, applying the
+    /// changes must be idempotent (RFC 8132)
+    pub async fn ipatch(url: &str, data: Vec<u8>) -> Result<CoapResponse, ClientError> {
+        Self::request(url, Method::IPatch, Some(data)).await
+    }
+
+    /// Execute a single ipatch request with a coap url using udp
+    pub async fn ipatch_with_timeout(
+        url: &str,
+        data: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<CoapResponse, ClientError> {
+        Self::request_with_timeout(url, Method::IPatch, Some(data), timeout).await
+    }
+
+    /// Execute a single request (GET, POST, PUT, DELETE, FETCH, PATCH, iPATCH) with a coap url.
+    /// Every call connects a new client, use a `CoapAgent` to reuse them
     pub async fn request(
         url: &str,
         method: Method,
         data: Option<Vec<u8>>,
-    ) -> IoResult<CoapResponse> {
-        let (domain, port, path, queries) = Self::parse_coap_url(url)?;
-        let client = UdpCoAPClient::new_udp((domain.as_str(), port)).await?;
-        let request = RequestBuilder::new(&path, method)
-            .queries(queries)
-            .domain(domain)
-            .data(data)
-            .build();
-        client.send(request).await
+    ) -> Result<CoapResponse, ClientError> {
+        Self::request_with_config(url, method, data, &ClientConfig::default()).await
     }
 
-    /// Execute a single request (GET, POST, PUT, DELETE) with a coap url and a specfic timeout
-    /// using udp
+    /// Execute a single request (GET, POST, PUT, DELETE, FETCH, PATCH, iPATCH) with a coap url and a specfic timeout
     pub async fn request_with_timeout(
         url: &str,
         method: Method,
         data: Option<Vec<u8>>,
         timeout: Duration,
-    ) -> IoResult<CoapResponse> {
-        let (domain, port, path, queries) = Self::parse_coap_url(url)?;
-        let mut client = UdpCoAPClient::new_udp((domain.as_str(), port)).await?;
-        client.set_receive_timeout(timeout);
-        let request = RequestBuilder::new(&path, method)
-            .queries(queries)
-            .domain(domain)
-            .data(data)
-            .build();
+    ) -> Result<CoapResponse, ClientError> {
+        let config = ClientConfig {
+            receive_timeout: Some(timeout),
+            ..Default::default()
+        };
+        Self::request_with_config(url, method, data, &config).await
+    }
 
-        client.send(request).await
+    /// Execute a single request (GET, POST, PUT, DELETE, FETCH, PATCH, iPATCH) with a coap url. coap urls are sent
+    /// over udp, coaps urls over DTLS with the DTLS config of the client config. With a proxy
+    /// in the config the request is sent to the proxy instead
+    pub async fn request_with_config(
+        url: &str,
+        method: Method,
+        data: Option<Vec<u8>>,
+        config: &ClientConfig,
+    ) -> Result<CoapResponse, ClientError> {
+        let (server, request) = config.route(url, method, data)?;
+        let client = SchemeClient::connect(&server, config).await?;
+        let response = client.send(request, config.receive_timeout).await?;
+        config.check_response(response)
     }
 
     /// Send a Request via the given transport, and receive a response.
     /// users are responsible for filling meaningful fields in the request
     /// this method supports blockwise requests
-    pub async fn send(&self, mut request: CoapRequest<SocketAddr>) -> IoResult<CoapResponse> {
-        let first_response = self.send_request(&mut request).await?;
-        request.response = Some(first_response);
-        self.receive(&mut request).await
+    pub async fn send(
+        &self,
+        mut request: CoapRequest<SocketAddr>,
+    ) -> Result<CoapResponse, ClientError> {
+        let (intercepted_by, intercepted) = self.before_request(&mut request).await?;
+        let mut response = match intercepted {
+            Some(response) => response,
+            None => {
+                self.maybe_request_block2_size(&mut request)?;
+                let first_response = self.send_request(&mut request).await?;
+                request.response = Some(first_response);
+                self.receive(&mut request).await?
+            }
+        };
+        self.after_response(intercepted_by, &request, &mut response)
+            .await?;
+        Ok(response)
+    }
+
+    /// Send a Request and return the response together with a stream of its body. The payload
+    /// of the returned response is empty: the body is in the stream, which fetches each Block2
+    /// block only when it is polled. The stream fails if the ETag of the resource changes
+    /// during the transfer. The maximum response size does not apply to streamed bodies
+    pub async fn send_streaming(
+        &self,
+        mut request: CoapRequest<SocketAddr>,
+    ) -> Result<(CoapResponse, BodyStream), ClientError> {
+        let (intercepted_by, intercepted) = self.before_request(&mut request).await?;
+        let mut response = match intercepted {
+            Some(response) => response,
+            None => {
+                self.maybe_request_block2_size(&mut request)?;
+                self.send_request(&mut request).await?
+            }
+        };
+        self.after_response(intercepted_by, &request, &mut response)
+            .await?;
+        let first_chunk = mem::take(&mut response.message.payload);
+        let next_block = response
+            .message
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .and_then(|x| x.ok())
+            .filter(|block2| block2.more)
+            .map(|block2| {
+                let mut next_block2 = block2.clone();
+                next_block2.num += 1;
+                next_block2.more = false;
+                next_block2
+            });
+        let state = BodyStreamState {
+            client: self.clone(),
+            request,
+            first_chunk: Some(first_chunk),
+            next_block,
+            etag: response.message.get_first_option(CoapOption::ETag).cloned(),
+        };
+        let body = stream::unfold(state, |mut state| async move {
+            if let Some(chunk) = state.first_chunk.take() {
+                return Some((Ok(chunk), state));
+            }
+            let next_block = state.next_block.take()?;
+            match Self::fetch_block2(&mut state, next_block).await {
+                Ok(chunk) => Some((Ok(chunk), state)),
+                Err(err) => Some((Err(err), state)),
+            }
+        });
+        Ok((response, Box::pin(body)))
+    }
+
+    /// fetches one block of a streamed body and remembers which block comes next
+    async fn fetch_block2(
+        state: &mut BodyStreamState<T>,
+        block2: BlockValue,
+    ) -> Result<Vec<u8>, ClientError> {
+        let request = &mut state.request;
+        request.message.header.message_id = state.client.gen_message_id();
+        request.message.clear_option(CoapOption::Block2);
+        request
+            .message
+            .add_option_as::<BlockValue>(CoapOption::Block2, block2.clone());
+        let mut response = state.client.send_single_request(request).await?;
+
+        // an error response carries no block of the representation
+        if *response.get_status() != Status::Content {
+            return Err(ClientError::response_status(&response.message));
+        }
+        if response.message.get_first_option(CoapOption::ETag) != state.etag.as_ref() {
+            return Err(ClientError::BlockNegotiation(
+                "resource changed during the transfer".to_string(),
+            ));
+        }
+        let received = response
+            .message
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .and_then(|x| x.ok())
+            .filter(|received| received.num == block2.num)
+            .ok_or_else(|| ClientError::BlockNegotiation("unexpected block2 option".to_string()))?;
+        if received.more {
+            let mut next_block2 = received.clone();
+            next_block2.num += 1;
+            next_block2.more = false;
+            state.next_block = Some(next_block2);
+        }
+        Ok(mem::take(&mut response.message.payload))
+    }
+
+    /// Discover the resources of the server by requesting `/.well-known/core` (RFC 6690).
+    /// The optional query filters the links, e.g. `rt=temperature` or `href=/sensors*`.
+    /// It is sent to the server, and applied to the links again for servers which ignore it
+    pub async fn discover(&self, query: Option<&str>) -> Result<Vec<Link>, ClientError> {
+        let query = Self::discovery_filter(query);
+        let response = self.send(Self::discovery_request(query)).await?;
+        Self::parse_discovery(response, query)
+    }
+
+    /// the filter of a discovery query, which may be given with the leading '?' of a URL
+    fn discovery_filter(query: Option<&str>) -> Option<&str> {
+        query.map(|query| query.strip_prefix('?').unwrap_or(query))
+    }
+
+    fn discovery_request(query: Option<&str>) -> CoapRequest<SocketAddr> {
+        RequestBuilder::new("/.well-known/core", Method::Get)
+            .queries(query.map(|query| query.as_bytes().to_vec()))
+            .build()
+    }
+
+    fn parse_discovery(
+        response: CoapResponse,
+        query: Option<&str>,
+    ) -> Result<Vec<Link>, ClientError> {
+        let response = response.error_for_status()?;
+        let document = std::str::from_utf8(&response.message.payload)
+            .map_err(|_| ClientError::MalformedResponse("link format is not utf-8".to_string()))?;
+        let mut links = link_format::parse(document)?;
+        if let Some(query) = query {
+            links.retain(|link| link.matches(query));
+        }
+        Ok(links)
     }
 
     pub async fn observe<H: FnMut(Message) + Send + 'static>(
         &self,
         resource_path: &str,
         handler: H,
-    ) -> IoResult<oneshot::Sender<ObserveMessage>>
+    ) -> Result<oneshot::Sender<ObserveMessage>, ClientError>
     where
         T: 'static + Send + Sync,
     {
@@ -690,7 +1898,7 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
         resource_path: &str,
         handler: H,
         timeout: Duration,
-    ) -> IoResult<oneshot::Sender<ObserveMessage>>
+    ) -> Result<oneshot::Sender<ObserveMessage>, ClientError>
     where
         T: 'static + Send + Sync,
     {
@@ -703,33 +1911,91 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
     /// requests. This method will add observe flags and a message id as a fallback
     /// Use this method if you plan on re-using the same client for requests
     pub async fn observe_with<H: FnMut(Message) + Send + 'static>(
+        &self,
+        request: CoapRequest<SocketAddr>,
+        handler: H,
+    ) -> Result<oneshot::Sender<ObserveMessage>, ClientError> {
+        self.observe_with_handlers(request, handler, |e| warn!("observe failed {:?}", e))
+            .await
+    }
+
+    /// same as observe_with, but the error handler is called once if the observation ends
+    /// because of an error, e.g. when the server resets it or answers with an error status
+    pub async fn observe_with_handlers<H, E>(
         &self,
         request: CoapRequest<SocketAddr>,
         mut handler: H,
-    ) -> IoResult<oneshot::Sender<ObserveMessage>> {
+        error_handler: E,
+    ) -> Result<oneshot::Sender<ObserveMessage>, ClientError>
+    where
+        H: FnMut(Message) + Send + 'static,
+        E: FnOnce(ClientError) + Send + 'static,
+    {
         let this = self.clone();
         let mut register_packet = request;
         if 0 == register_packet.message.header.message_id {
             register_packet.message.header.message_id = self.gen_message_id();
         }
+        self.ensure_token(&mut register_packet).await;
+        // the remaining blocks of a notification are fetched with the registration request
+        // without the Observe option. The payload of a FETCH selects the representation,
+        // so it is sent again (RFC 8132 section 2.5)
+        let mut block_request = register_packet.clone();
+        block_request.message.clear_option(CoapOption::Observe);
+        block_request.message.clear_option(CoapOption::Block2);
+        if *block_request.get_method() != Method::Fetch {
+            block_request.message.payload = Vec::new();
+        }
         register_packet.set_observe_flag(ObserveOption::Register);
 
         let req_token = register_packet.message.get_token().to_vec();
+        let mut registration_message_id = register_packet.message.header.message_id;
         let resource_path = register_packet.get_path();
-        let response = self.send(register_packet).await?;
-        if *response.get_status() != Status::Content {
-            return Err(Error::new(ErrorKind::NotFound, "the resource not found"));
-        }
+        let mut reregister_packet = register_packet.message.clone();
+        // notifications sent right after the registration response reach the observation
         let (tx_observe, mut rx_observe) = unbounded_channel();
         self.transport
             .synchronizer
-            .set_sender(req_token.clone(), tx_observe)
+            .start_handover(req_token.clone(), tx_observe)
+            .await?;
+        let response = self.send(register_packet).await;
+        self.transport
+            .synchronizer
+            .finish_handover(&req_token)
+            .await;
+        let response = match response {
+            Ok(response) if *response.get_status() == Status::Content => response,
+            Ok(response) => {
+                self.transport.synchronizer.remove_sender(&req_token).await;
+                return Err(ClientError::response_status(&response.message));
+            }
+            Err(e) => {
+                self.transport.synchronizer.remove_sender(&req_token).await;
+                return Err(e);
+            }
+        };
+        // a reset of the registration ends the observation
+        self.transport
+            .synchronizer
+            .set_message_id(registration_message_id, req_token.clone())
             .await;
+        let mut error_handler = Some(error_handler);
+        let mut freshness = ObserveFreshness::default();
+        if let Some(Ok(sequence)) = response.message.get_observe_value() {
+            freshness.is_fresh(sequence, Instant::now());
+        }
+        // re-register with the same token once Max-Age passes without a notification,
+        // RFC 7641 section 3.3.1
+        let parameters = self.transport.parameters;
+        let mut reregister_at = Self::deadline_after(Self::reregistration_delay(&response.message));
+        let mut reregistration_timeouts = None;
 
         handler(response.message);
 
         let (tx, rx) = oneshot::channel();
         let observe_path = String::from(resource_path);
+        let (tx_fetch, mut rx_fetch) = unbounded_channel();
+        let notification_count = Arc::new(AtomicUsize::new(0));
 
         tokio::spawn(async move {
             let mut rx_pinned: Pin<
@@ -742,12 +2008,108 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
             loop {
                 tokio::select! {
                     sock_rx = rx_observe.recv() => {
-                        Self::receive_and_handle_message_observe(sock_rx?, &mut handler).await;
+                        let Some(socket_result) = sock_rx else {
+                            break;
+                        };
+                        let notification = match Self::receive_message_observe(socket_result) {
+                            Ok(Some(notification)) => notification,
+                            Ok(None) => continue,
+                            Err(e) => {
+                                // the observation was reset by the server or the transport failed
+                                this.forget_observation(&req_token, registration_message_id).await;
+                                if let Some(error_handler) = error_handler.take() {
+                                    error_handler(e.into());
+                                }
+                                break;
+                            }
+                        };
+                        // an error response, e.g. to a re-registration, ends the observation,
+                        // RFC 7641 section 3.2
+                        if !is_success(&notification) {
+                            this.forget_observation(&req_token, registration_message_id).await;
+                            if let Some(error_handler) = error_handler.take() {
+                                error_handler(ClientError::response_status(&notification));
+                            }
+                            break;
+                        }
+                        // the server may have restarted its sequence numbers
+                        if notification.header.get_type() == MessageType::Acknowledgement
+                            && notification.header.message_id == registration_message_id
+                        {
+                            freshness = ObserveFreshness::default();
+                        }
+                        if let Some(Ok(sequence)) = notification.get_observe_value() {
+                            if !freshness.is_fresh(sequence, Instant::now()) {
+                                debug!("dropping reordered notification {}", sequence);
+                                continue;
+                            }
+                        }
+                        reregister_at = Self::deadline_after(Self::reregistration_delay(&notification));
+                        reregistration_timeouts = None;
+                        // a newer notification supersedes the blocks still being fetched
+                        let current = notification_count.fetch_add(1, Ordering::Relaxed) + 1;
+                        if !Self::is_partial_notification(&notification) {
+                            handler(notification);
+                            continue;
+                        }
+                        let fetcher = this.clone();
+                        let mut request = block_request.clone();
+                        let notification_count = notification_count.clone();
+                        let tx_fetch = tx_fetch.clone();
+                        tokio::spawn(async move {
+                            let result = fetcher
+                                .fetch_notification_blocks(
+                                    &mut request,
+                                    notification,
+                                    &notification_count,
+                                    current,
+                                )
+                                .await;
+                            let _ = tx_fetch.send((current, result));
+                        });
+                    }
+                    Some((fetched, result)) = rx_fetch.recv() => {
+                        if fetched != notification_count.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        match result {
+                            Ok(Some(notification)) => handler(notification),
+                            Ok(None) => {}
+                            Err(e) => warn!("could not fetch the blocks of a notification: {}", e),
+                        }
+                    }
+                    _ = tokio::time::sleep_until(reregister_at) => {
+                        let timeouts = reregistration_timeouts
+                            .get_or_insert_with(|| parameters.retransmission_timeouts());
+                        let Some(wait) = timeouts.next() else {
+                            this.forget_observation(&req_token, registration_message_id).await;
+                            if let Some(error_handler) = error_handler.take() {
+                                error_handler(ClientError::Timeout);
+                            }
+                            break;
+                        };
+                        this.transport
+                            .synchronizer
+                            .remove_message_id(registration_message_id, &req_token)
+                            .await;
+                        registration_message_id = this.gen_message_id();
+                        reregister_packet.header.message_id = registration_message_id;
+                        this.transport
+                            .synchronizer
+                            .set_message_id(registration_message_id, req_token.clone())
+                            .await;
+                        debug!("re-registering observation of {}", observe_path);
+                        if let Err(e) = this.transport.send_message(&reregister_packet).await {
+                            warn!("could not re-register observation: {}", e);
+                        }
+                        reregister_at = Self::deadline_after(wait);
                     }
                     observe = &mut rx_pinned => {
                         match observe {
                             Ok(ObserveMessage::Terminate) => {
-                                this.terminate_observe(&observe_path, req_token).await;
+                                // the deregistration uses the token of the observation
+                                this.forget_observation(&req_token, registration_message_id).await;
+                                this.terminate_observe(reregister_packet.clone()).await;
                                 break;
                             }
                             // if the receiver is dropped, we change the future to wait forever
@@ -760,17 +2122,69 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
 
                 }
             }
-            Some(())
         });
         return Ok(tx);
     }
 
-    async fn terminate_observe(&self, observe_path: &str, req_token: Vec<u8>) {
+    /// stops routing the notifications and resets of an observation to its handlers
+    async fn forget_observation(&self, token: &[u8], registration_message_id: u16) {
+        self.transport.synchronizer.remove_sender(token).await;
+        self.transport
+            .synchronizer
+            .remove_message_id(registration_message_id, token)
+            .await;
+    }
+
+    /// how long to wait for the next notification before re-registering, based on the
+    /// Max-Age of the last one
+    fn reregistration_delay(notification: &Message) -> Duration {
+        max_age(notification).saturating_add(OBSERVE_REREGISTRATION_LEEWAY)
+    }
+
+    /// the instant after the delay, or a distant one if the clock cannot represent it
+    fn deadline_after(delay: Duration) -> Instant {
+        let now = Instant::now();
+        now.checked_add(delay)
+            .unwrap_or_else(|| now + OBSERVE_DISTANT_FUTURE)
+    }
+
+    /// Observe a resource and receive its notifications as a stream. The registration fails
+    /// like a request does, later errors such as a notification with an error status, a reset
+    /// or a lost observation end the stream. Dropping the stream deregisters the observation
+    pub async fn observe_stream(
+        &self,
+        request: CoapRequest<SocketAddr>,
+    ) -> Result<ObserveStream, ClientError>
+    where
+        T: 'static + Send + Sync,
+    {
+        let (tx, notifications) = unbounded_channel();
+        let tx_error = tx.clone();
+        let terminate = self
+            .observe_with_handlers(
+                request,
+                move |message| {
+                    let _ = tx.send(Ok(Notification { message }));
+                },
+                move |e| {
+                    let _ = tx_error.send(Err(e));
+                },
+            )
+            .await?;
+        Ok(ObserveStream {
+            notifications,
+            terminate: Some(terminate),
+            finished: false,
+        })
+    }
+
+    /// deregisters with the registration request, so that the method, options and payload
+    /// identify the same observation, e.g. for a FETCH (RFC 7641 section 3.6)
+    async fn terminate_observe(&self, registration: Message) {
         let mut deregister_packet = CoapRequest::<SocketAddr>::new();
+        deregister_packet.message = registration;
         deregister_packet.message.header.message_id = self.gen_message_id();
         deregister_packet.set_observe_flag(ObserveOption::Deregister);
-        deregister_packet.set_path(observe_path);
-        deregister_packet.message.set_token(req_token);
 
         let _ = self
             .transport
@@ -778,21 +2192,68 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
             .await;
     }
 
-    async fn receive_and_handle_message_observe<H: FnMut(Message) + Send + 'static>(
-        socket_result: IoResult<Packet>,
-        handler: &mut H,
-    ) {
-        match socket_result {
-            Ok(packet) => {
-                handler(packet.message);
-            }
-            Err(e) => match e.kind() {
-                ErrorKind::WouldBlock => {
-                    info!("Observe timeout");
+    /// returns the notification for the handler, errors end the observation
+    fn receive_message_observe(socket_result: IoResult<Packet>) -> IoResult<Option<Message>> {
+        let packet = socket_result?;
+        if is_empty_ack(&packet) {
+            return Ok(None);
+        }
+        Ok(Some(packet.message))
+    }
+
+    /// whether a notification only carries the first block of the representation,
+    /// RFC 7959 section 2.6
+    fn is_partial_notification(notification: &Message) -> bool {
+        notification
+            .get_first_option_as::<BlockValue>(CoapOption::Block2)
+            .and_then(|x| x.ok())
+            .is_some_and(|block2| block2.more)
+    }
+
+    /// fetches the remaining blocks of a notification with a new token. Returns `None` when
+    /// a newer notification arrives before all blocks are fetched
+    async fn fetch_notification_blocks(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+        notification: Message,
+        notification_count: &AtomicUsize,
+        current: usize,
+    ) -> Result<Option<Message>, ClientError> {
+        let token = self
+            .transport
+            .synchronizer
+            .generate_token(self.token_length)
+            .await;
+        request.message.set_token(token.clone());
+        request.response = Some(CoapResponse {
+            message: notification.clone(),
+        });
+        let mut block2_state = BlockState::default();
+        let fetched = async {
+            while self.intercept_response(request, &mut block2_state)? {
+                if notification_count.load(Ordering::Relaxed) != current {
+                    return Ok(false);
                 }
-                _ => warn!("observe failed {:?}", e),
-            },
-        };
+                request.message.header.message_id = self.gen_message_id();
+                let resp = self.send_single_request(request).await?;
+                request.response = Some(resp);
+            }
+            Ok::<_, ClientError>(true)
+        }
+        .await;
+        self.transport.synchronizer.release_token(&token).await;
+        if !fetched? {
+            return Ok(None);
+        }
+        // the representation is delivered with the options of the notification
+        let mut notification = notification;
+        notification.clear_option(CoapOption::Block2);
+        notification.payload = request
+            .response
+            .take()
+            .map(|response| response.message.payload)
+            .unwrap_or_default();
+        Ok(Some(notification))
     }
 
     /// sends a request through the transport. If a request is confirmable, it will attempt
@@ -803,7 +2264,7 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
     pub async fn send_single_request(
         &self,
         request: &CoapRequest<SocketAddr>,
-    ) -> IoResult<CoapResponse> {
+    ) -> Result<CoapResponse, ClientError> {
         let response = self
             .transport
             .do_request_response_for_packet(&Packet {address:None, message:request.message.to_owned()})
@@ -813,90 +2274,241 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
 
     /// low-level method to send a a request supporting block1 option based on
     /// the block size set in the client
-    async fn send_request(&self, request: &mut CoapRequest<SocketAddr>) -> IoResult<CoapResponse> {
-        let request_length = request.message.payload.len();
-        if request_length <= self.block1_size {
-            if 0 == request.message.header.message_id {
-                request.message.header.message_id = self.gen_message_id();
-            }
-            return self.send_single_request(request).await;
-        }
-        let payload = std::mem::take(&mut request.message.payload);
-        let mut it = payload.chunks(self.block1_size).enumerate().peekable();
-        let mut result = Err(Error::new(ErrorKind::Other, "unknown error occurred"));
+    async fn send_request(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+    ) -> Result<CoapResponse, ClientError> {
+        self.ensure_token(request).await;
+        let result = self.send_request_with_token(request).await;
+        // a generated token is still reserved if the request failed before it was sent
+        self.transport
+            .synchronizer
+            .release_token(request.message.get_token())
+            .await;
+        result
+    }
+
+    async fn send_request_with_token(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+    ) -> Result<CoapResponse, ClientError> {
+        let request_length = request.message.payload.len();
+        // payloads which do not fit in one datagram are sent in blocks right away
+        let too_large = request_length > 0
+            && matches!(
+                request.message.to_bytes(),
+                Err(MessageError::InvalidPacketLength)
+            );
+        if request_length > self.block1_size || too_large {
+            let block_size = usize::min(self.block1_size, Self::MAX_PAYLOAD_BLOCK);
+            return self.send_blockwise_request(request, block_size).await;
+        }
+        if 0 == request.message.header.message_id {
+            request.message.header.message_id = self.gen_message_id();
+        }
+        let resp = self.send_single_request(request).await?;
+        // the server may ask for a blockwise transfer instead, RFC 7959 section 2.9.3
+        if *resp.get_status() != Status::RequestEntityTooLarge || request_length == 0 {
+            return Ok(resp);
+        }
+        match Self::block1_size_hint(&resp) {
+            Some(block_size) => self.send_blockwise_request(request, block_size).await,
+            None => Ok(resp),
+        }
+    }
+
+    /// sends the payload of the request in blocks, using smaller blocks whenever the server
+    /// asks for them as described in RFC 7959 section 2.5
+    async fn send_blockwise_request(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+        block_size: usize,
+    ) -> Result<CoapResponse, ClientError> {
+        let payload = std::mem::take(&mut request.message.payload);
+        let mut block_size = block_size;
+        let mut offset = 0;
 
-        while let Some((idx, elem)) = it.next() {
-            let more_blocks = it.peek().is_some();
-            let block = BlockValue::new(idx, more_blocks, self.block1_size)
-                .map_err(|_| Error::new(ErrorKind::Other, "could not set block size"))?;
+        loop {
+            let end = usize::min(offset + block_size, payload.len());
+            let more_blocks = end < payload.len();
+            let block =
+                BlockValue::new(offset / block_size, more_blocks, block_size).map_err(|_| {
+                    ClientError::BlockNegotiation("could not set block size".to_string())
+                })?;
 
             request.message.clear_option(CoapOption::Block1);
             request
                 .message
-                .add_option_as::<BlockValue>(CoapOption::Block1, block.clone());
-            request.message.payload = elem.to_vec();
+                .add_option_as::<BlockValue>(CoapOption::Block1, block);
+            request.message.payload = payload[offset..end].to_vec();
 
             request.message.header.message_id = self.gen_message_id();
             let resp = self.send_single_request(request).await?;
-            // continue receiving responses until last element
-            if it.peek().is_some() {
-                let maybe_block1 = resp
-                    .message
-                    .get_first_option_as::<BlockValue>(CoapOption::Block1)
-                    .ok_or(Error::new(
-                        ErrorKind::Unsupported,
-                        "endpoint does not support blockwise transfers. Try setting block1_size to a larger value",
-                    ))?;
-                let block1_resp = maybe_block1.map_err(|_| {
-                    Error::new(
-                        ErrorKind::InvalidData,
-                        "endpoint responded with invalid block",
+            if *resp.get_status() == Status::RequestEntityTooLarge {
+                match Self::block1_size_hint(&resp) {
+                    // the server dropped the transfer, start over with smaller blocks
+                    Some(size) if size < block_size => {
+                        block_size = size;
+                        offset = 0;
+                        continue;
+                    }
+                    _ => return Ok(resp),
+                }
+            }
+            // continue sending blocks until last element
+            if !more_blocks {
+                // requests for the following Block2 blocks carry neither Block1 nor a
+                // payload, RFC 7959 section 3.3
+                request.message.clear_option(CoapOption::Block1);
+                request.message.payload.clear();
+                return Ok(resp);
+            }
+            let maybe_block1 = resp
+                .message
+                .get_first_option_as::<BlockValue>(CoapOption::Block1)
+                .ok_or_else(|| {
+                    ClientError::BlockNegotiation(
+                        "endpoint does not support blockwise transfers. Try setting block1_size to a larger value".to_string(),
                     )
                 })?;
-                //TODO: negotiate smaller block size
-                if block1_resp.size_exponent != block.size_exponent {
-                    return Err(Error::new(
-                        ErrorKind::Unsupported,
-                        "negotiating block size is currently unsupported",
-                    ));
-                }
+            let block1_resp = maybe_block1.map_err(|_| {
+                ClientError::BlockNegotiation("endpoint responded with invalid block".to_string())
+            })?;
+            // the following blocks are numbered according to the smaller size
+            if block1_resp.size() < block_size {
+                block_size = block1_resp.size();
+            }
+            offset = end;
+        }
+    }
+
+    /// the block size a server asks for in a 4.13 response, taken from its Block1 option
+    /// or from the largest block fitting into its Size1 option
+    fn block1_size_hint(response: &CoapResponse) -> Option<usize> {
+        let block1 = response
+            .message
+            .get_first_option_as::<BlockValue>(CoapOption::Block1)
+            .and_then(|x| x.ok());
+        if let Some(block1) = block1 {
+            return Some(block1.size());
+        }
+        let size1 = decode_uint(response.message.get_first_option(CoapOption::Size1)?);
+        let mut block_size = Self::MAX_PAYLOAD_BLOCK;
+        while block_size > 16 && block_size as u64 > size1 {
+            block_size /= 2;
+        }
+        Some(block_size)
+    }
+
+    /// runs the request hooks of the interceptors. Returns how many of them saw the request
+    /// and the response of the interceptor which answered it, if any
+    async fn before_request(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+    ) -> Result<(usize, Option<CoapResponse>), ClientError> {
+        for (index, interceptor) in self.interceptors.iter().enumerate() {
+            if let Some(response) = interceptor.on_request(request).await? {
+                return Ok((index + 1, Some(response)));
             }
-            result = Ok(resp);
         }
-        return result;
+        Ok((self.interceptors.len(), None))
+    }
+
+    /// runs the response hooks of the first `count` interceptors in reverse order
+    async fn after_response(
+        &self,
+        count: usize,
+        request: &CoapRequest<SocketAddr>,
+        response: &mut CoapResponse,
+    ) -> Result<(), ClientError> {
+        for interceptor in self.interceptors[..count].iter().rev() {
+            interceptor.on_response(request, response).await?;
+        }
+        Ok(())
+    }
+
+    /// asks for the configured block2 size in the first request, RFC 7959 section 2.4
+    fn maybe_request_block2_size(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+    ) -> Result<(), ClientError> {
+        let Some(block2_size) = self.block2_size else {
+            return Ok(());
+        };
+        if request
+            .message
+            .get_first_option(CoapOption::Block2)
+            .is_some()
+        {
+            return Ok(());
+        }
+        let block2 = BlockValue::new(0, false, block2_size)
+            .map_err(|_| ClientError::BlockNegotiation("could not set block size".to_string()))?;
+        request
+            .message
+            .add_option_as::<BlockValue>(CoapOption::Block2, block2);
+        Ok(())
     }
 
     /// Receive a response support block-wise.
-    async fn receive(&self, request: &mut CoapRequest<SocketAddr>) -> IoResult<CoapResponse> {
+    async fn receive(
+        &self,
+        request: &mut CoapRequest<SocketAddr>,
+    ) -> Result<CoapResponse, ClientError> {
         let mut block2_state = BlockState::default();
-        loop {
-            match Self::intercept_response(request, &mut block2_state) {
-                Ok(true) => {
-                    request.message.header.message_id = self.gen_message_id();
-                    let resp = self.send_single_request(request).await?;
-                    request.response = Some(resp);
-                }
-                Err(err) => {
-                    error!("intercept response error: {:?}", err);
-                    return Err(Error::new(ErrorKind::Interrupted, "packet error"));
-                }
-                Ok(false) => {
-                    break;
-                }
-            }
+        while self.intercept_response(request, &mut block2_state)? {
+            request.message.header.message_id = self.gen_message_id();
+            let resp = self.send_single_request(request).await?;
+            request.response = Some(resp);
         }
         Ok(CoapResponse {
             message: request.response.as_ref().unwrap().message.clone(),
         })
     }
 
-    /// Set the receive timeout.
+    /// Set the receive timeout. For confirmable requests this is the ACK_TIMEOUT, which is
+    /// randomized and doubled on every retransmission.
     pub fn set_receive_timeout(&mut self, dur: Duration) {
-        self.transport.timeout = dur;
+        self.set_transmission_parameters(TransmissionParameters {
+            ack_timeout: dur,
+            ..self.transport.parameters
+        });
     }
 
+    /// Set the number of transmissions of a confirmable request, including the first one.
     pub fn set_transport_retries(&mut self, num_retries: usize) {
-        self.transport.retries = num_retries;
+        self.set_transmission_parameters(TransmissionParameters {
+            max_retransmit: num_retries.saturating_sub(1),
+            ..self.transport.parameters
+        });
+    }
+
+    /// Set the transmission parameters (ACK_TIMEOUT, ACK_RANDOM_FACTOR, MAX_RETRANSMIT and
+    /// NSTART) used for requests. A changed NSTART only applies to this client and to clones
+    /// created afterwards. Duplicate detection, which is shared by all clones, uses the
+    /// parameters set last.
+    pub fn set_transmission_parameters(&mut self, parameters: TransmissionParameters) {
+        if parameters.nstart != self.transport.parameters.nstart {
+            self.transport.outstanding = Arc::new(Semaphore::new(parameters.nstart.max(1)));
+        }
+        self.transport.parameters = parameters;
+        self.transport.synchronizer.set_parameters(parameters);
+    }
+
+    pub fn transmission_parameters(&self) -> TransmissionParameters {
+        self.transport.parameters
+    }
+
+    /// Set how long to wait for a separate response once the server acknowledged a request
+    /// with an empty ACK. Defaults to EXCHANGE_LIFETIME of the transmission parameters.
+    pub fn set_separate_response_timeout(&mut self, dur: Duration) {
+        self.transport.separate_response_timeout = Some(dur);
+    }
+
+    /// Set the length of the random tokens generated for requests without a token.
+    /// The length is clamped to 1 to 8 bytes, default is 4 bytes
+    pub fn set_token_length(&mut self, token_length: usize) {
+        self.token_length = token_length.clamp(1, 8);
     }
 
     /// Set the maximum size for a block1 request. Default is 1024 bytes
@@ -904,16 +2516,38 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
         self.block1_size = block1_max_bytes;
     }
 
-    fn parse_coap_url(url: &str) -> IoResult<(String, u16, String, Option<Vec<u8>>)> {
+    /// Set the block2 size requested from the server in the first request, which is useful
+    /// for small MTUs or little memory. By default the server chooses the block size
+    pub fn set_block2_size(&mut self, block2_max_bytes: Option<usize>) {
+        self.block2_size = block2_max_bytes;
+    }
+
+    /// Add an interceptor which runs after the ones added before
+    pub fn add_interceptor<I: Interceptor + 'static>(&mut self, interceptor: I) {
+        self.interceptors.push(Arc::new(interceptor));
+    }
+
+    /// Set the maximum size of a response reassembled from blocks. Larger responses fail with
+    /// `ClientError::ResponseTooLarge`. Default is 1 MiB
+    pub fn set_max_response_size(&mut self, max_bytes: usize) {
+        self.max_response_size = max_bytes;
+    }
+
+    pub(crate) fn parse_coap_url(url: &str) -> Result<CoapUrl, ClientError> {
         let url_params = match Url::parse(url) {
             Ok(url_params) => url_params,
-            Err(_) => return Err(Error::new(ErrorKind::InvalidInput, "url error")),
+            Err(e) => return Err(ClientError::Url(e.to_string())),
+        };
+        let scheme = match url_params.scheme() {
+            "coap" => Scheme::Coap,
+            "coaps" => Scheme::Coaps,
+            other => return Err(ClientError::Url(format!("unsupported scheme {}", other))),
         };
 
         let host = match url_params.host_str() {
-            Some("") => return Err(Error::new(ErrorKind::InvalidInput, "host error")),
+            Some("") => return Err(ClientError::Url("empty host".to_string())),
             Some(h) => h,
-            None => return Err(Error::new(ErrorKind::InvalidInput, "host error")),
+            None => return Err(ClientError::Url("missing host".to_string())),
         };
         let host = Regex::new(r"^\[(.*?)]$")
             .unwrap()
@@ -922,14 +2556,32 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
 
         let port = match url_params.port() {
             Some(p) => p,
-            None => 5683,
+            None => scheme.default_port(),
         };
 
         let path = url_params.path().to_string();
 
         let queries = url_params.query().map(|q| q.as_bytes().to_vec());
 
-        return Ok((host.to_string(), port, path, queries));
+        return Ok(CoapUrl {
+            scheme,
+            host: host.to_string(),
+            port,
+            path,
+            queries,
+        });
+    }
+
+    /// sets a random token which is not in use on requests without a token
+    async fn ensure_token(&self, request: &mut CoapRequest<SocketAddr>) {
+        if request.message.get_token().is_empty() {
+            let token = self
+                .transport
+                .synchronizer
+                .generate_token(self.token_length)
+                .await;
+            request.message.set_token(token);
+        }
     }
 
     fn gen_message_id(&self) -> u16 {
@@ -938,10 +2590,11 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
     }
 
     fn intercept_response(
+        &self,
         request: &mut CoapRequest<SocketAddr>,
         state: &mut BlockState,
-    ) -> std::result::Result<bool, HandlingError> {
-        let block2_handled = Self::maybe_handle_response_block2(request, state)?;
+    ) -> Result<bool, ClientError> {
+        let block2_handled = self.maybe_handle_response_block2(request, state)?;
         if block2_handled {
             return Ok(true);
         }
@@ -950,9 +2603,10 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
     }
 
     fn maybe_handle_response_block2(
+        &self,
         request: &mut CoapRequest<SocketAddr>,
         state: &mut BlockState,
-    ) -> std::result::Result<bool, HandlingError> {
+    ) -> Result<bool, ClientError> {
         let response = request.response.as_ref().unwrap();
         let maybe_block2 = response
             .message
@@ -966,13 +2620,19 @@ impl<T: ClientTransport + 'static> CoAPClient<T> {
             let cached_payload = state.cached_payload.as_mut().unwrap();
 
             let payload_offset = usize::from(block2.num) * block2.size();
+            let payload_end = payload_offset + response.message.payload.len();
+            if payload_end > self.max_response_size {
+                return Err(ClientError::ResponseTooLarge {
+                    max_size: self.max_response_size,
+                });
+            }
             extending_splice(
                 cached_payload,
-                payload_offset..payload_offset + block2.size(),
+                payload_offset..payload_end,
                 response.message.payload.iter().copied(),
-                16 * 1024,
+                self.max_response_size,
             )
-            .map_err(HandlingError::internal)?;
+            .map_err(ClientError::BlockNegotiation)?;
 
             if block2.more {
                 request.message.clear_option(CoapOption::Block2);
@@ -1003,15 +2663,20 @@ mod test {
 
     use tokio::time;
 
-    use crate::server::test::spawn_server;
+    use crate::server::test::{ack, spawn_server, spawn_udp_server, TestPeer};
 
     use super::super::*;
     use super::*;
+    use futures::StreamExt;
     use std::io::ErrorKind;
     use std::ops::DerefMut;
     use std::str;
     use std::sync::atomic::{AtomicU32, Ordering};
     use std::time::Duration;
+
+    /// the initial transmission and its retransmissions
+    const DEFAULT_NUM_RETRIES: usize = DEFAULT_MAX_RETRANSMIT + 1;
+
     #[test]
     fn test_parse_coap_url_good_url() {
         assert!(UdpCoAPClient::parse_coap_url("coap://127.0.0.1").is_ok());
@@ -1023,12 +2688,157 @@ mod test {
         assert!(UdpCoAPClient::parse_coap_url("coap://127.0.0.1/?hello=world").is_ok());
     }
 
+    #[test]
+    fn test_parse_coap_url_scheme() {
+        let url = UdpCoAPClient::parse_coap_url("coap://127.0.0.1/a").unwrap();
+        assert_eq!((url.scheme, url.port), (Scheme::Coap, 5683));
+        let url = UdpCoAPClient::parse_coap_url("coaps://127.0.0.1/a").unwrap();
+        assert_eq!((url.scheme, url.port), (Scheme::Coaps, 5684));
+        let url = UdpCoAPClient::parse_coap_url("coaps://127.0.0.1:6000/a").unwrap();
+        assert_eq!((url.scheme, url.port), (Scheme::Coaps, 6000));
+    }
+
+    #[tokio::test]
+    async fn test_coaps_url_without_dtls_config() {
+        let error = UdpCoAPClient::get("coaps://127.0.0.1/hello")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClientError::DtlsNotConfigured));
+    }
+
+    /// a forward proxy answering every request with the status, reporting the requests
+    async fn spawn_proxy(status: Status) -> (String, UnboundedReceiver<Message>) {
+        let (tx, rx) = unbounded_channel();
+        let proxy_addr = spawn_udp_server(move |request, _| {
+            let response = ack(&request, status);
+            tx.send(request).unwrap();
+            Some(response)
+        })
+        .await;
+        (format!("coap://{}", proxy_addr), rx)
+    }
+
+    fn proxy_config(url: String, proxy_uri: bool) -> ClientConfig {
+        ClientConfig {
+            proxy: Some(ProxyConfig { url, proxy_uri }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_scheme() {
+        let (proxy_url, mut rx) = spawn_proxy(Status::Content).await;
+        let config = proxy_config(proxy_url, false);
+        UdpCoAPClient::request_with_config(
+            "coap://device.local/sensors/temp?unit=c",
+            Method::Get,
+            None,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        let request = rx.recv().await.unwrap();
+        let option = |option| request.get_first_option(option).cloned();
+        assert_eq!(option(CoapOption::ProxyScheme), Some(b"coap".to_vec()));
+        assert_eq!(option(CoapOption::UriHost), Some(b"device.local".to_vec()));
+        assert_eq!(option(CoapOption::UriPort), Some(vec![0x16, 0x33]));
+        assert_eq!(option(CoapOption::UriQuery), Some(b"unit=c".to_vec()));
+        let path: Vec<_> = request
+            .get_option(CoapOption::UriPath)
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(path, vec![b"sensors".to_vec(), b"temp".to_vec()]);
+        assert!(request.get_option(CoapOption::ProxyUri).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_uri() {
+        let (proxy_url, mut rx) = spawn_proxy(Status::Content).await;
+        let config = proxy_config(proxy_url, true);
+        let target = "coap://device.local:6000/sensors/temp";
+        UdpCoAPClient::request_with_config(target, Method::Get, None, &config)
+            .await
+            .unwrap();
+
+        let request = rx.recv().await.unwrap();
+        assert_eq!(
+            request.get_first_option(CoapOption::ProxyUri),
+            Some(&target.as_bytes().to_vec())
+        );
+        for option in [
+            CoapOption::UriHost,
+            CoapOption::UriPort,
+            CoapOption::UriPath,
+            CoapOption::ProxyScheme,
+        ] {
+            assert!(request.get_option(option).is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_errors() {
+        let (proxy_url, _rx) = spawn_proxy(Status::ProxyingNotSupported).await;
+        let error = UdpCoAPClient::request_with_config(
+            "coap://device.local/a",
+            Method::Get,
+            None,
+            &proxy_config(proxy_url, false),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, ClientError::ProxyingNotSupported));
+
+        let (proxy_url, _rx) = spawn_proxy(Status::BadGateway).await;
+        let error = UdpCoAPClient::request_with_config(
+            "coap://device.local/a",
+            Method::Get,
+            None,
+            &proxy_config(proxy_url, true),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, ClientError::BadGateway));
+    }
+
+    #[test]
+    fn test_encode_uint() {
+        assert_eq!(encode_uint(0), Vec::<u8>::new());
+        assert_eq!(encode_uint(60), vec![60]);
+        assert_eq!(encode_uint(5683), vec![0x16, 0x33]);
+        assert_eq!(decode_uint(&encode_uint(1 << 40)), 1 << 40);
+    }
+
+    #[test]
+    fn test_retransmission_timeouts() {
+        let parameters = TransmissionParameters::default();
+        let timeouts: Vec<Duration> = parameters.retransmission_timeouts().collect();
+        assert_eq!(timeouts.len(), parameters.max_retransmit + 1);
+        assert!(timeouts[0] >= parameters.ack_timeout);
+        assert!(timeouts[0] <= parameters.ack_timeout.mul_f64(parameters.ack_random_factor));
+        for pair in timeouts.windows(2) {
+            assert_eq!(pair[1], pair[0] * 2);
+        }
+    }
+
+    #[test]
+    fn test_default_exchange_lifetime() {
+        let parameters = TransmissionParameters::default();
+        assert_eq!(parameters.max_transmit_span(), Duration::from_secs(45));
+        assert_eq!(parameters.exchange_lifetime(), Duration::from_secs(247));
+        assert_eq!(parameters.non_lifetime(), Duration::from_secs(145));
+    }
+
     #[test]
     fn test_parse_coap_url_bad_url() {
         assert!(UdpCoAPClient::parse_coap_url("coap://127.0.0.1:65536").is_err());
         assert!(UdpCoAPClient::parse_coap_url("coap://").is_err());
         assert!(UdpCoAPClient::parse_coap_url("coap://:5683").is_err());
         assert!(UdpCoAPClient::parse_coap_url("127.0.0.1").is_err());
+        assert!(UdpCoAPClient::parse_coap_url("coap+tcp://127.0.0.1").is_err());
+        assert!(UdpCoAPClient::parse_coap_url("http://127.0.0.1").is_err());
     }
 
     async fn request_handler(req: Box<CoapRequest<SocketAddr>>) -> Box<CoapRequest<SocketAddr>> {
@@ -1038,8 +2848,10 @@ mod test {
 
     #[test]
     fn test_parse_queries() {
-        if let Ok((_, _, _, Some(queries))) =
-            UdpCoAPClient::parse_coap_url("coap://127.0.0.1/?hello=world&test1=test2")
+        if let Ok(CoapUrl {
+            queries: Some(queries),
+            ..
+        }) = UdpCoAPClient::parse_coap_url("coap://127.0.0.1/?hello=world&test1=test2")
         {
             assert_eq!("hello=world&test1=test2".as_bytes().to_vec(), queries);
         } else {
@@ -1068,7 +2880,7 @@ mod test {
         )
         .await
         .unwrap_err();
-        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert!(matches!(error, ClientError::Timeout));
     }
 
     #[tokio::test]
@@ -1230,7 +3042,7 @@ mod test {
             )
             .await;
         let err = resp.unwrap_err();
-        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(matches!(err, ClientError::BlockNegotiation(_)));
         //we now set the block size to make sure it is sent in a single request
         client.set_block1_size(10_000_000);
 
@@ -1262,6 +3074,98 @@ mod test {
         client.send_all_coap(&request, 0x4).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_multicast_collect() {
+        let group = TestPeer::bind().await;
+        let other = TestPeer::bind().await;
+        let client = UdpCoAPClient::new_with_specific_source("127.0.0.1:0", group.local_addr())
+            .await
+            .unwrap();
+        let responders = vec![group.local_addr(), other.local_addr()];
+        tokio::spawn(async move {
+            let (request, client_addr) = group.recv().await;
+            assert_eq!(request.header.get_type(), MessageType::NonConfirmable);
+            assert!(!request.get_token().is_empty());
+            // the group answers twice, the other server once
+            let responses = [
+                (&group, 1, "first"),
+                (&other, 2, "other"),
+                (&group, 3, "again"),
+            ];
+            for (socket, message_id, payload) in responses {
+                let mut response = Message::new();
+                response.header.set_type(MessageType::NonConfirmable);
+                response.header.code = MessageClass::Response(Status::Content);
+                response.header.message_id = message_id;
+                response.set_token(request.get_token().to_vec());
+                response.payload = payload.as_bytes().to_vec();
+                socket.send(&response, client_addr).await;
+            }
+        });
+
+        let request = RequestBuilder::new("/", Method::Get)
+            .confirmable(true)
+            .build();
+        let responses = client
+            .multicast_collect(request, Duration::from_millis(300))
+            .await
+            .unwrap();
+        let responses: Vec<_> = responses
+            .into_iter()
+            .map(|(address, response)| (address, response.message.payload))
+            .collect();
+        assert_eq!(
+            responses,
+            vec![
+                (responders[0], b"first".to_vec()),
+                (responders[1], b"other".to_vec())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover() {
+        let server_port = spawn_server("127.0.0.1:0", |mut req| async {
+            let query = req
+                .message
+                .get_first_option(CoapOption::UriQuery)
+                .cloned()
+                .unwrap_or_default();
+            let payload = match (req.get_path().as_str(), query.as_slice()) {
+                (".well-known/core", b"rt=temperature") => {
+                    // large enough to be sent blockwise
+                    let mut links: Vec<_> = (0..100)
+                        .map(|i| format!("</sensors/{}>;rt=\"temperature\";ct=0;obs", i))
+                        .collect();
+                    // a server ignoring the filter
+                    links.push("</light>;rt=light".to_string());
+                    links.join(",")
+                }
+                _ => "not found".to_string(),
+            };
+            req.response.as_mut().unwrap().message.payload = payload.into_bytes();
+            req
+        })
+        .recv()
+        .await
+        .unwrap();
+
+        let client = UdpCoAPClient::new_udp(format!("127.0.0.1:{}", server_port))
+            .await
+            .unwrap();
+        let links = client.discover(Some("rt=temperature")).await.unwrap();
+        assert_eq!(links.len(), 100);
+        assert_eq!(links[42].target, "/sensors/42");
+        assert_eq!(links[42].resource_types, vec!["temperature"]);
+        assert_eq!(links[42].content_formats, vec![0]);
+        assert!(links[42].observable);
+        let links = client.discover(Some("?rt=temperature")).await.unwrap();
+        assert_eq!(links.len(), 100);
+
+        let error = client.discover(None).await.unwrap_err();
+        assert!(matches!(error, ClientError::MalformedResponse(_)));
+    }
+
     struct FaultyUdp {
         pub udp: UdpTransport,
         pub num_fails: u32,
@@ -1319,22 +3223,18 @@ mod test {
         .unwrap();
 
         let server_addr = format!("127.0.0.1:{}", server_port);
-        let mut client = get_faulty_client(
-            &server_addr,
-            CoapClientTransport::<FaultyUdp>::DEFAULT_NUM_RETRIES as u32 + 1,
-        )
-        .await;
+        let mut client = get_faulty_client(&server_addr, DEFAULT_NUM_RETRIES as u32 + 1).await;
         let request_gen = || {
             RequestBuilder::new("/Rust", Method::Get)
                 .domain(server_addr.clone())
                 .build()
         };
         let error = client.send(request_gen()).await.unwrap_err();
-        assert_eq!(error.kind(), ErrorKind::Other);
+        assert!(matches!(error, ClientError::Transport(ref e) if e.kind() == ErrorKind::Other));
         //this request will work, we do this to reset the state of the faulty udp
         client.send(request_gen()).await.unwrap();
 
-        client.set_transport_retries(CoapClientTransport::<UdpTransport>::DEFAULT_NUM_RETRIES + 2);
+        client.set_transport_retries(DEFAULT_NUM_RETRIES + 2);
         let resp = client.send(request_gen()).await.unwrap();
 
         assert_eq!(resp.message.payload, b"Rust".to_vec());
@@ -1361,74 +3261,1164 @@ mod test {
         assert!(req.is_err());
     }
 
-    async fn do_wait_request<T: ClientTransport + 'static>(
-        client: Arc<CoAPClient<T>>,
-        path: &str,
-        token: Vec<u8>,
-        wait_ms: u64,
-    ) -> IoResult<CoapResponse> {
-        let mut request = CoapRequest::new();
-        request.message.header.set_version(1);
-        request
-            .message
-            .header
-            .set_type(coap_lite::MessageType::Confirmable);
-        request.message.header.set_code("0.01");
-        request.message.header.message_id = 1;
-        request.message.set_token(token);
-        request
-            .message
-            .add_option(CoapOption::UriPath, path.as_bytes().to_vec());
-        request.message.payload = wait_ms.to_string().into();
-
-        return client.send(request).await;
-    }
+    #[tokio::test]
+    async fn test_separate_response() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        let (tx, mut rx) = unbounded_channel();
+        tokio::spawn(async move {
+            let (request, client_addr) = server.recv().await;
+            let mut empty_ack = Message::new();
+            empty_ack.header.set_type(MessageType::Acknowledgement);
+            empty_ack.header.code = MessageClass::Empty;
+            empty_ack.header.message_id = request.header.message_id;
+            server.send(&empty_ack, client_addr).await;
+
+            time::sleep(Duration::from_millis(500)).await;
+            let mut response = Message::new();
+            response.header.set_type(MessageType::Confirmable);
+            response.header.code = MessageClass::Response(Status::Content);
+            response.header.message_id = 4242;
+            response.set_token(request.get_token().to_vec());
+            response.payload = b"separate".to_vec();
+            server.send(&response, client_addr).await;
+            // forward everything received afterwards, retransmissions would show up first
+            loop {
+                tx.send(server.recv().await.0).unwrap();
+            }
+        });
 
-    async fn wait_handler(mut req: Box<CoapRequest<SocketAddr>>) -> Box<CoapRequest<SocketAddr>> {
-        let uri_path_list = req.message.get_option(CoapOption::UriPath).unwrap().clone();
-        let payload = str::from_utf8(&req.message.payload).unwrap();
-        let to_wait_ms: u64 = payload.parse().unwrap();
-        time::sleep(Duration::from_millis(to_wait_ms)).await;
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_receive_timeout(Duration::from_millis(50));
+        let resp = client
+            .send(RequestBuilder::new("/separate", Method::Get).build())
+            .await
+            .unwrap();
+        assert_eq!(resp.message.payload, b"separate".to_vec());
 
-        match req.response {
-            Some(ref mut response) => {
-                response.message.payload = uri_path_list.front().unwrap().clone();
-            }
-            _ => {}
-        }
-        return req;
+        let ack = timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ack.header.get_type(), MessageType::Acknowledgement);
+        assert_eq!(ack.header.message_id, 4242);
     }
-    /// run 2 clients using the same transport and receive an answer
-    /// in the expected order without interference
+
     #[tokio::test]
-    async fn test_multiple_clients_same_socket() {
-        let server_port = spawn_server("127.0.0.1:0", wait_handler)
-            .recv()
+    async fn test_duplicate_detection() {
+        let server = TestPeer::bind().await;
+        let client = UdpCoAPClient::new_with_specific_source("127.0.0.1:0", server.local_addr())
             .await
             .unwrap();
+        let client_addr = client.transport.transport.socket.local_addr().unwrap();
+        let request = RequestBuilder::new("/", Method::Get)
+            .token(Some(vec![7]))
+            .build();
+        let mut receiver = client.create_receiver_for(&request).await;
+
+        let messages: Vec<(u16, &[u8])> =
+            vec![(1, &b"first"[..]), (1, &b"first"[..]), (2, &b"second"[..])];
+        for (message_id, payload) in messages {
+            let mut message = Message::new();
+            message.header.set_type(MessageType::Confirmable);
+            message.header.code = MessageClass::Response(Status::Content);
+            message.header.message_id = message_id;
+            message.set_token(vec![7]);
+            message.payload = payload.to_vec();
+            server.send(&message, client_addr).await;
+        }
 
-        let client = Arc::new(
-            UdpCoAPClient::new_udp(format!("127.0.0.1:{}", server_port))
+        assert_eq!(receiver.receive().await.unwrap().message.payload, b"first");
+        assert_eq!(receiver.receive().await.unwrap().message.payload, b"second");
+
+        // the duplicate is acknowledged again but not delivered
+        let mut acked = vec![];
+        for _ in 0..3 {
+            let (ack, _) = timeout(Duration::from_secs(1), server.recv())
                 .await
-                .unwrap(),
-        );
-        let mut b = tokio::spawn(do_wait_request(client.clone(), "/bar", vec![1], 500));
-        let a = tokio::spawn(do_wait_request(client.clone(), "/foo", vec![2], 50));
+                .unwrap();
+            assert_eq!(ack.header.get_type(), MessageType::Acknowledgement);
+            acked.push(ack.header.message_id);
+        }
+        assert_eq!(acked, vec![1, 1, 2]);
+    }
 
-        tokio::select! {
-            a_first = a => {
-            let a_first = a_first.unwrap().unwrap();
-            assert_eq!(a_first.message.payload, b"/foo".to_vec());
-            assert_eq!(a_first.message.get_token(), vec![2]);
+    #[tokio::test]
+    async fn test_duplicate_lifetime_follows_parameters() {
+        let mut client = UdpCoAPClient::new_udp("127.0.0.1:5683").await.unwrap();
+        let mut duplicates =
+            DuplicateDetector::new(client.transport.synchronizer.parameters.subscribe());
+        client.set_receive_timeout(Duration::from_secs(60));
+
+        let mut message = Message::new();
+        message.header.set_type(MessageType::Confirmable);
+        message.header.message_id = 1;
+        duplicates.record(
+            &Packet {
+                address: None,
+                message,
             },
-            _b_first = &mut b => {
-                panic!("should not happen");
+            None,
+        );
+        let default_lifetime = TransmissionParameters::default().exchange_lifetime();
+        assert!(duplicates.received[&(None, 1)].expires > Instant::now() + default_lifetime);
+    }
 
-            }
-        }
-        let b_end = b.await.unwrap().expect("should receive a response");
-        assert_eq!(b_end.message.payload, b"/bar".to_vec());
-        assert_eq!(b_end.message.get_token(), vec![1]);
+    #[tokio::test]
+    async fn test_reset_by_peer() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        tokio::spawn(async move {
+            let (request, client_addr) = server.recv().await;
+            let mut reset = Message::new();
+            reset.header.set_type(MessageType::Reset);
+            reset.header.message_id = request.header.message_id;
+            server.send(&reset, client_addr).await;
+        });
+
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_receive_timeout(Duration::from_secs(1));
+        let start = Instant::now();
+        let error = client
+            .send(RequestBuilder::new("/reset", Method::Get).build())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClientError::Reset));
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "request was retransmitted"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reset_ends_observation() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        tokio::spawn(async move {
+            let (request, client_addr) = server.recv().await;
+            let mut response = ack(&request, Status::Content);
+            response.set_observe_value(1);
+            server.send(&response, client_addr).await;
+
+            time::sleep(Duration::from_millis(100)).await;
+            let mut reset = Message::new();
+            reset.header.set_type(MessageType::Reset);
+            reset.header.message_id = request.header.message_id;
+            server.send(&reset, client_addr).await;
+        });
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let _observe = client
+            .observe_with_handlers(
+                RequestBuilder::new("/reset", Method::Get)
+                    .token(Some(vec![1]))
+                    .build(),
+                |_| {},
+                move |e| tx.send(e).unwrap(),
+            )
+            .await
+            .unwrap();
+        let error = timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(error, ClientError::Reset));
+    }
+
+    #[test]
+    fn test_client_error_io_conversion() {
+        let error: Error = ClientError::Timeout.into();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert!(matches!(ClientError::from(error), ClientError::Timeout));
+
+        let error: Error = ClientError::ResponseTooLarge { max_size: 10 }.into();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(matches!(
+            ClientError::from(error),
+            ClientError::ResponseTooLarge { max_size: 10 }
+        ));
+
+        let error = ClientError::from(Error::new(ErrorKind::ConnectionReset, "reset"));
+        assert!(matches!(error, ClientError::Reset));
+        let error = ClientError::from(Error::new(ErrorKind::BrokenPipe, "closed"));
+        assert!(
+            matches!(error, ClientError::Transport(ref e) if e.kind() == ErrorKind::BrokenPipe)
+        );
+        let error: Error = error.into();
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_observe_freshness() {
+        let now = Instant::now();
+        let mut freshness = ObserveFreshness::default();
+        assert!(freshness.is_fresh(10, now));
+        assert!(freshness.is_fresh(11, now));
+        // reordered
+        assert!(!freshness.is_fresh(10, now));
+        assert!(!freshness.is_fresh(11, now));
+        // too far ahead is treated as older
+        assert!(!freshness.is_fresh(11 + (1 << 23), now));
+        assert!(freshness.is_fresh(12, now));
+    }
+
+    #[test]
+    fn test_observe_freshness_wraparound() {
+        let now = Instant::now();
+        let mut freshness = ObserveFreshness::default();
+        assert!(freshness.is_fresh((1 << 24) - 2, now));
+        assert!(freshness.is_fresh(1, now));
+        assert!(!freshness.is_fresh((1 << 24) - 1, now));
+        assert!(freshness.is_fresh(2, now));
+    }
+
+    #[test]
+    fn test_observe_freshness_after_128_seconds() {
+        let now = Instant::now();
+        let mut freshness = ObserveFreshness::default();
+        assert!(freshness.is_fresh(100, now));
+        assert!(!freshness.is_fresh(50, now + Duration::from_secs(127)));
+        // the server may have restarted its sequence numbers
+        assert!(freshness.is_fresh(50, now + Duration::from_secs(129)));
+        assert!(!freshness.is_fresh(49, now + Duration::from_secs(130)));
+    }
+
+    /// adds a query to every request and records the status of every response
+    struct QueryInterceptor {
+        statuses: Arc<std::sync::Mutex<Vec<Status>>>,
+    }
+
+    #[async_trait]
+    impl Interceptor for QueryInterceptor {
+        async fn on_request(
+            &self,
+            request: &mut CoapRequest<SocketAddr>,
+        ) -> Result<Option<CoapResponse>, ClientError> {
+            request
+                .message
+                .add_option(CoapOption::UriQuery, b"auth=secret".to_vec());
+            Ok(None)
+        }
+
+        async fn on_response(
+            &self,
+            _request: &CoapRequest<SocketAddr>,
+            response: &mut CoapResponse,
+        ) -> Result<(), ClientError> {
+            self.statuses
+                .lock()
+                .unwrap()
+                .push(response.get_status().clone());
+            response.message.payload.make_ascii_uppercase();
+            Ok(())
+        }
+    }
+
+    /// answers every request itself
+    struct CannedInterceptor;
+
+    #[async_trait]
+    impl Interceptor for CannedInterceptor {
+        async fn on_request(
+            &self,
+            _request: &mut CoapRequest<SocketAddr>,
+        ) -> Result<Option<CoapResponse>, ClientError> {
+            let mut message = Message::new();
+            message.header.code = MessageClass::Response(Status::Content);
+            message.payload = b"canned".to_vec();
+            Ok(Some(CoapResponse { message }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interceptors() {
+        let server_port = server::test::spawn_server("127.0.0.1:0", |mut req| async {
+            let query = req.message.get_first_option(CoapOption::UriQuery).cloned();
+            req.response.as_mut().unwrap().message.payload = query.unwrap_or_default();
+            req
+        })
+        .recv()
+        .await
+        .unwrap();
+
+        let statuses = Arc::new(std::sync::Mutex::new(vec![]));
+        let mut client = UdpCoAPClient::new_udp(format!("127.0.0.1:{}", server_port))
+            .await
+            .unwrap();
+        client.add_interceptor(QueryInterceptor {
+            statuses: statuses.clone(),
+        });
+        let resp = client
+            .send(RequestBuilder::new("/query", Method::Get).build())
+            .await
+            .unwrap();
+        assert_eq!(resp.message.payload, b"AUTH=SECRET");
+        assert_eq!(*statuses.lock().unwrap(), vec![Status::Content]);
+
+        // the canned response skips the server but still passes the earlier interceptors
+        client.add_interceptor(CannedInterceptor);
+        let resp = client
+            .send(RequestBuilder::new("/query", Method::Get).build())
+            .await
+            .unwrap();
+        assert_eq!(resp.message.payload, b"CANNED");
+        assert_eq!(statuses.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reset_unknown_token() {
+        let server = TestPeer::bind().await;
+        let client = UdpCoAPClient::new_with_specific_source("127.0.0.1:0", server.local_addr())
+            .await
+            .unwrap();
+        let client_addr = client.transport.transport.socket.local_addr().unwrap();
+
+        for (message_type, message_id) in [
+            (MessageType::Confirmable, 200),
+            (MessageType::NonConfirmable, 201),
+        ] {
+            let mut notification = Message::new();
+            notification.header.set_type(message_type);
+            notification.header.code = MessageClass::Response(Status::Content);
+            notification.header.message_id = message_id;
+            notification.set_token(vec![0xde, 0xad]);
+            notification.set_observe_value(5);
+            server.send(&notification, client_addr).await;
+            let (reply, _) = timeout(Duration::from_secs(2), server.recv())
+                .await
+                .unwrap();
+            assert_eq!(reply.header.get_type(), MessageType::Reset);
+            assert_eq!(reply.header.message_id, message_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observe_stream() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        let (tx_deregister, mut rx_deregister) = unbounded_channel();
+        tokio::spawn(async move {
+            let (register, client_addr) = server.recv().await;
+            let notify = |mut message: Message, sequence: u32| {
+                message.header.code = MessageClass::Response(Status::Content);
+                message.set_token(register.get_token().to_vec());
+                message.set_observe_value(sequence);
+                message.payload = sequence.to_string().into_bytes();
+                message
+            };
+            let response = ack(&register, Status::Content);
+            server.send(&notify(response, 1), client_addr).await;
+            let mut notification = Message::new();
+            notification.header.set_type(MessageType::NonConfirmable);
+            notification.header.message_id = 100;
+            server.send(&notify(notification, 2), client_addr).await;
+
+            let (deregister, _) = server.recv().await;
+            tx_deregister.send(deregister).unwrap();
+        });
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let mut stream = client
+            .observe_stream(RequestBuilder::new("/stream", Method::Get).build())
+            .await
+            .unwrap();
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!((first.sequence(), first.payload()), (Some(1), &b"1"[..]));
+        let second = stream.next().await.unwrap().unwrap();
+        assert_eq!((second.sequence(), second.payload()), (Some(2), &b"2"[..]));
+
+        drop(stream);
+        let deregister = timeout(Duration::from_secs(2), rx_deregister.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(deregister.get_observe_value().unwrap().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_observe_fetch() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        let (tx_requests, mut rx_requests) = unbounded_channel();
+        tokio::spawn(async move {
+            let (register, client_addr) = server.recv().await;
+            let mut response = ack(&register, Status::Content);
+            response.set_observe_value(1);
+            server.send(&response, client_addr).await;
+            tx_requests.send(register).unwrap();
+
+            tx_requests.send(server.recv().await.0).unwrap();
+        });
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let request = RequestBuilder::new("/sensors", Method::Fetch)
+            .data(Some(b"temperature".to_vec()))
+            .build();
+        let mut stream = client.observe_stream(request).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().sequence(), Some(1));
+        drop(stream);
+
+        for observe in [0, 1] {
+            let request = timeout(Duration::from_secs(2), rx_requests.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(request.header.code, MessageClass::Request(Method::Fetch));
+            assert_eq!(request.get_observe_value().unwrap().unwrap(), observe);
+            assert_eq!(
+                request.get_first_option(CoapOption::UriPath),
+                Some(&b"sensors".to_vec())
+            );
+            assert_eq!(request.payload, b"temperature");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block1_then_block2() {
+        let mut body = vec![];
+        let server_addr = spawn_udp_server(move |request, _| {
+            let block1 = request
+                .get_first_option_as::<BlockValue>(CoapOption::Block1)
+                .map(|block1| block1.unwrap());
+            let block2 = request
+                .get_first_option_as::<BlockValue>(CoapOption::Block2)
+                .map(|block2| block2.unwrap());
+            let response = match (block1, block2) {
+                (Some(block1), None) if block1.more => {
+                    body.extend_from_slice(&request.payload);
+                    let mut response = ack(&request, Status::Continue);
+                    response.add_option_as(CoapOption::Block1, block1);
+                    response
+                }
+                (Some(block1), None) => {
+                    body.extend_from_slice(&request.payload);
+                    assert_eq!(body.len(), 40);
+                    let mut response = ack(&request, Status::Content);
+                    response.add_option_as(CoapOption::Block1, block1);
+                    response
+                        .add_option_as(CoapOption::Block2, BlockValue::new(0, true, 16).unwrap());
+                    response.payload = vec![1; 16];
+                    response
+                }
+                // the follow up only asks for the next block
+                (None, Some(block2)) => {
+                    assert_eq!(block2.num, 1);
+                    assert!(request.payload.is_empty());
+                    let mut response = ack(&request, Status::Content);
+                    response
+                        .add_option_as(CoapOption::Block2, BlockValue::new(1, false, 16).unwrap());
+                    response.payload = vec![2; 4];
+                    response
+                }
+                other => panic!("unexpected blocks {:?}", other),
+            };
+            Some(response)
+        })
+        .await;
+
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_block1_size(16);
+        let resp = client
+            .send(
+                RequestBuilder::new("/query", Method::Fetch)
+                    .data(Some(vec![0; 40]))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let mut expected = vec![1; 16];
+        expected.extend_from_slice(&[2; 4]);
+        assert_eq!(resp.message.payload, expected);
+    }
+
+    #[tokio::test]
+    async fn test_observe_stream_ends_on_error_status() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        tokio::spawn(async move {
+            let (register, client_addr) = server.recv().await;
+            server
+                .send(&expiring_notification(&register, 1), client_addr)
+                .await;
+            let mut deleted = Message::new();
+            deleted.header.set_type(MessageType::NonConfirmable);
+            deleted.header.code = MessageClass::Response(Status::NotFound);
+            deleted.header.message_id = 100;
+            deleted.set_token(register.get_token().to_vec());
+            server.send(&deleted, client_addr).await;
+            loop {
+                server.recv().await;
+            }
+        });
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let mut stream = client
+            .observe_stream(RequestBuilder::new("/deleted", Method::Get).build())
+            .await
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::ResponseStatus {
+                status: Status::NotFound,
+                ..
+            }
+        ));
+        assert!(stream.terminate.is_none());
+        assert!(stream.next().await.is_none());
+    }
+
+    /// replies to an observe registration with a notification that expires immediately
+    fn expiring_notification(register: &Message, sequence: u32) -> Message {
+        let mut response = ack(register, Status::Content);
+        response.set_observe_value(sequence);
+        response.add_option(CoapOption::MaxAge, vec![]);
+        response
+    }
+
+    #[tokio::test]
+    async fn test_observe_reregistration() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        let (tx_token, mut rx_token) = unbounded_channel();
+        tokio::spawn(async move {
+            for sequence in 1..=2 {
+                let (register, client_addr) = server.recv().await;
+                assert_eq!(register.get_observe_value().unwrap().unwrap(), 0);
+                tx_token.send(register.get_token().to_vec()).unwrap();
+                server
+                    .send(&expiring_notification(&register, sequence), client_addr)
+                    .await;
+            }
+        });
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let _observe = client
+            .observe("/expiring", move |msg| tx.send(msg).unwrap())
+            .await
+            .unwrap();
+        let notification = rx.recv().await.unwrap();
+        assert_eq!(notification.get_observe_value().unwrap().unwrap(), 1);
+        let notification = timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification.get_observe_value().unwrap().unwrap(), 2);
+        let first_token = rx_token.recv().await.unwrap();
+        assert_eq!(rx_token.recv().await.unwrap(), first_token);
+    }
+
+    #[tokio::test]
+    async fn test_observation_lost() {
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        tokio::spawn(async move {
+            let (register, client_addr) = server.recv().await;
+            server
+                .send(&expiring_notification(&register, 1), client_addr)
+                .await;
+            // the server is gone, re-registrations are never answered
+            loop {
+                server.recv().await;
+            }
+        });
+
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_transmission_parameters(TransmissionParameters {
+            ack_timeout: Duration::from_millis(50),
+            max_retransmit: 1,
+            ..Default::default()
+        });
+        let (tx, mut rx) = unbounded_channel();
+        let _observe = client
+            .observe_with_handlers(
+                RequestBuilder::new("/lost", Method::Get).build(),
+                |_| {},
+                move |e| tx.send(e).unwrap(),
+            )
+            .await
+            .unwrap();
+        let error = timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(error, ClientError::Timeout));
+    }
+
+    #[tokio::test]
+    async fn test_reregistration_error_status() {
+        let (tx_requests, mut rx_requests) = unbounded_channel();
+        let mut registered = false;
+        let server_addr = spawn_udp_server(move |request, _| {
+            if !registered {
+                registered = true;
+                return Some(expiring_notification(&request, 1));
+            }
+            // the resource is gone when the client re-registers
+            tx_requests.send(()).unwrap();
+            Some(ack(&request, Status::NotFound))
+        })
+        .await;
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let (tx_notifications, mut rx_notifications) = unbounded_channel();
+        let _observe = client
+            .observe_with_handlers(
+                RequestBuilder::new("/gone", Method::Get).build(),
+                move |msg| tx_notifications.send(msg).unwrap(),
+                move |e| tx.send(e).unwrap(),
+            )
+            .await
+            .unwrap();
+        let error = timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            error,
+            ClientError::ResponseStatus {
+                status: Status::NotFound,
+                ..
+            }
+        ));
+        // only the registration reached the handler and the client stopped re-registering
+        let registration = rx_notifications.recv().await.unwrap();
+        assert_eq!(registration.get_observe_value().unwrap().unwrap(), 1);
+        assert!(rx_notifications.recv().await.is_none());
+        rx_requests.recv().await.unwrap();
+        let reregistration = timeout(Duration::from_secs(3), rx_requests.recv()).await;
+        assert!(reregistration.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_blockwise_notification() {
+        let representation: Vec<u8> = (0..40).collect();
+        let server = TestPeer::bind().await;
+        let server_addr = server.local_addr();
+        let body = representation.clone();
+        tokio::spawn(async move {
+            let (register, client_addr) = server.recv().await;
+            let mut response = ack(&register, Status::Content);
+            response.set_observe_value(1);
+            server.send(&response, client_addr).await;
+
+            // the notification only carries the first block
+            let mut notification = Message::new();
+            notification.header.set_type(MessageType::NonConfirmable);
+            notification.header.code = MessageClass::Response(Status::Content);
+            notification.header.message_id = 100;
+            notification.set_token(register.get_token().to_vec());
+            notification.set_observe_value(2);
+            let block = BlockValue::new(0, true, 16).unwrap();
+            notification.add_option_as::<BlockValue>(CoapOption::Block2, block);
+            notification.payload = body[..16].to_vec();
+            server.send(&notification, client_addr).await;
+
+            loop {
+                let (request, client_addr) = server.recv().await;
+                assert!(request.get_observe_value().is_none());
+                assert_ne!(request.get_token(), register.get_token());
+                let num = match request.get_first_option_as::<BlockValue>(CoapOption::Block2) {
+                    Some(Ok(block)) => usize::from(block.num),
+                    _ => 0,
+                };
+                let end = usize::min((num + 1) * 16, body.len());
+                let mut response = ack(&request, Status::Content);
+                let block = BlockValue::new(num, end < body.len(), 16).unwrap();
+                response.add_option_as::<BlockValue>(CoapOption::Block2, block);
+                response.payload = body[num * 16..end].to_vec();
+                server.send(&response, client_addr).await;
+            }
+        });
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let _observe = client
+            .observe("/large", move |msg| tx.send(msg).unwrap())
+            .await
+            .unwrap();
+        let registration = rx.recv().await.unwrap();
+        assert_eq!(registration.get_observe_value().unwrap().unwrap(), 1);
+        let notification = timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification.get_observe_value().unwrap().unwrap(), 2);
+        assert_eq!(notification.payload, representation);
+    }
+
+    async fn do_wait_request<T: ClientTransport + 'static>(
+        client: Arc<CoAPClient<T>>,
+        path: &str,
+        token: Vec<u8>,
+        wait_ms: u64,
+    ) -> Result<CoapResponse, ClientError> {
+        let mut request = CoapRequest::new();
+        request.message.header.set_version(1);
+        request
+            .message
+            .header
+            .set_type(coap_lite::MessageType::Confirmable);
+        request.message.header.set_code("0.01");
+        request.message.header.message_id = 1;
+        request.message.set_token(token);
+        request
+            .message
+            .add_option(CoapOption::UriPath, path.as_bytes().to_vec());
+        request.message.payload = wait_ms.to_string().into();
+
+        return client.send(request).await;
+    }
+
+    async fn wait_handler(mut req: Box<CoapRequest<SocketAddr>>) -> Box<CoapRequest<SocketAddr>> {
+        let uri_path_list = req.message.get_option(CoapOption::UriPath).unwrap().clone();
+        let payload = str::from_utf8(&req.message.payload).unwrap();
+        let to_wait_ms: u64 = payload.parse().unwrap();
+        time::sleep(Duration::from_millis(to_wait_ms)).await;
+
+        match req.response {
+            Some(ref mut response) => {
+                response.message.payload = uri_path_list.front().unwrap().clone();
+            }
+            _ => {}
+        }
+        return req;
+    }
+    /// run 2 clients using the same transport and receive an answer
+    /// in the expected order without interference
+    #[tokio::test]
+    async fn test_multiple_clients_same_socket() {
+        let server_port = spawn_server("127.0.0.1:0", wait_handler)
+            .recv()
+            .await
+            .unwrap();
+
+        let mut client = UdpCoAPClient::new_udp(format!("127.0.0.1:{}", server_port))
+            .await
+            .unwrap();
+        client.set_transmission_parameters(TransmissionParameters {
+            nstart: 2,
+            ..Default::default()
+        });
+        let client = Arc::new(client);
+        let mut b = tokio::spawn(do_wait_request(client.clone(), "/bar", vec![1], 500));
+        let a = tokio::spawn(do_wait_request(client.clone(), "/foo", vec![2], 50));
+
+        tokio::select! {
+            a_first = a => {
+            let a_first = a_first.unwrap().unwrap();
+            assert_eq!(a_first.message.payload, b"/foo".to_vec());
+            assert_eq!(a_first.message.get_token(), vec![2]);
+            },
+            _b_first = &mut b => {
+                panic!("should not happen");
+
+            }
+        }
+        let b_end = b.await.unwrap().expect("should receive a response");
+        assert_eq!(b_end.message.payload, b"/bar".to_vec());
+        assert_eq!(b_end.message.get_token(), vec![1]);
+    }
+
+    /// a server accepting block1 transfers which asks for blocks of at most `block_size`
+    /// bytes and answers requests without a block1 option with 4.13 and a size1 hint
+    async fn spawn_block1_server(block_size: usize) -> (SocketAddr, UnboundedReceiver<Vec<u8>>) {
+        let (tx, rx) = unbounded_channel();
+        let mut received = vec![];
+        let server_addr = spawn_udp_server(move |request, _| {
+            let Some(Ok(block)) = request.get_first_option_as::<BlockValue>(CoapOption::Block1)
+            else {
+                let mut response = ack(&request, Status::RequestEntityTooLarge);
+                response.add_option(
+                    CoapOption::Size1,
+                    (block_size as u16).to_be_bytes().to_vec(),
+                );
+                return Some(response);
+            };
+            let offset = usize::from(block.num) * block.size();
+            received.truncate(offset);
+            received.extend_from_slice(&request.payload);
+            let mut response = if block.more {
+                ack(&request, Status::Continue)
+            } else {
+                tx.send(mem::take(&mut received)).unwrap();
+                ack(&request, Status::Changed)
+            };
+            let size = usize::min(block.size(), block_size);
+            let reply = BlockValue::new(usize::from(block.num), block.more, size);
+            response.add_option_as::<BlockValue>(CoapOption::Block1, reply.unwrap());
+            Some(response)
+        })
+        .await;
+        (server_addr, rx)
+    }
+
+    #[tokio::test]
+    async fn test_block1_smaller_block_size() {
+        let (server_addr, mut rx) = spawn_block1_server(256).await;
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let resp = client
+            .send(
+                RequestBuilder::new("/upload", Method::Put)
+                    .data(Some(payload.clone()))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(*resp.get_status(), Status::Changed);
+        assert_eq!(rx.recv().await.unwrap(), payload);
+    }
+
+    #[tokio::test]
+    async fn test_block1_after_request_entity_too_large() {
+        let (server_addr, mut rx) = spawn_block1_server(512).await;
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_block1_size(4096);
+        // fits in one datagram, but is larger than the server accepts
+        let payload: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let resp = client
+            .send(
+                RequestBuilder::new("/upload", Method::Put)
+                    .data(Some(payload.clone()))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(*resp.get_status(), Status::Changed);
+        assert_eq!(rx.recv().await.unwrap(), payload);
+    }
+
+    #[tokio::test]
+    async fn test_block1_when_payload_exceeds_datagram() {
+        let (server_addr, mut rx) = spawn_block1_server(512).await;
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_block1_size(4096);
+        let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let resp = client
+            .send(
+                RequestBuilder::new("/upload", Method::Put)
+                    .data(Some(payload.clone()))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(*resp.get_status(), Status::Changed);
+        assert_eq!(rx.recv().await.unwrap(), payload);
+    }
+
+    /// a server returning `body` in blocks of the size requested by the client, reporting
+    /// every requested block
+    async fn spawn_block2_server(body: Vec<u8>) -> (SocketAddr, UnboundedReceiver<BlockValue>) {
+        let (tx, rx) = unbounded_channel();
+        let server_addr = spawn_udp_server(move |request, _| {
+            let requested = match request.get_first_option_as::<BlockValue>(CoapOption::Block2) {
+                Some(Ok(block)) => block,
+                _ => BlockValue::new(0, false, 1024).unwrap(),
+            };
+            let offset = usize::from(requested.num) * requested.size();
+            let end = usize::min(offset + requested.size(), body.len());
+            let more = end < body.len();
+            let mut response = ack(&request, Status::Content);
+            let block = BlockValue::new(usize::from(requested.num), more, requested.size());
+            response.add_option_as::<BlockValue>(CoapOption::Block2, block.unwrap());
+            response.payload = body[offset..end].to_vec();
+            tx.send(requested).unwrap();
+            Some(response)
+        })
+        .await;
+        (server_addr, rx)
+    }
+
+    #[tokio::test]
+    async fn test_block2_early_negotiation() {
+        let body: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let (server_addr, mut rx) = spawn_block2_server(body.clone()).await;
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_block2_size(Some(64));
+        let resp = client
+            .send(RequestBuilder::new("/firmware", Method::Get).build())
+            .await
+            .unwrap();
+        assert_eq!(resp.message.payload, body);
+        let first = rx.recv().await.unwrap();
+        assert_eq!((first.num, first.size()), (0, 64));
+    }
+
+    #[tokio::test]
+    async fn test_max_response_size() {
+        let body = vec![0x42; 3000];
+        let (server_addr, _rx) = spawn_block2_server(body).await;
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_max_response_size(2048);
+        let error = client
+            .send(RequestBuilder::new("/firmware", Method::Get).build())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::ResponseTooLarge { max_size: 2048 }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_streaming() {
+        let body: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let (server_addr, mut rx) = spawn_block2_server(body.clone()).await;
+        let mut client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        client.set_block2_size(Some(128));
+        let (resp, mut stream) = client
+            .send_streaming(RequestBuilder::new("/firmware", Method::Get).build())
+            .await
+            .unwrap();
+        assert_eq!(resp.get_status(), &Status::Content);
+        assert!(resp.message.payload.is_empty());
+        assert_eq!(rx.recv().await.unwrap().num, 0);
+
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first, body[..128]);
+        // the second block is only requested once the stream is polled again
+        assert!(rx.try_recv().is_err());
+
+        let mut received = first;
+        while let Some(chunk) = stream.next().await {
+            received.extend(chunk.unwrap());
+        }
+        assert_eq!(received, body);
+        assert_eq!(rx.recv().await.unwrap().num, 1);
+        assert_eq!(rx.recv().await.unwrap().num, 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_streaming_etag_changed() {
+        let mut etag = 0u8;
+        let server_addr = spawn_udp_server(move |request, _| {
+            let mut response = ack(&request, Status::Content);
+            let num = match request.get_first_option_as::<BlockValue>(CoapOption::Block2) {
+                Some(Ok(block)) => usize::from(block.num),
+                _ => 0,
+            };
+            let block = BlockValue::new(num, true, 16).unwrap();
+            response.add_option_as::<BlockValue>(CoapOption::Block2, block);
+            response.add_option(CoapOption::ETag, vec![etag]);
+            response.payload = vec![0; 16];
+            etag += 1;
+            Some(response)
+        })
+        .await;
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let (_, mut stream) = client
+            .send_streaming(RequestBuilder::new("/changing", Method::Get).build())
+            .await
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(error, ClientError::BlockNegotiation(_)));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_streaming_error_status() {
+        let server_addr = spawn_udp_server(|request, _| {
+            // only the first block is served
+            if request.get_first_option(CoapOption::Block2).is_some() {
+                return Some(ack(&request, Status::ServiceUnavailable));
+            }
+            let mut response = ack(&request, Status::Content);
+            let block = BlockValue::new(0, true, 16).unwrap();
+            response.add_option_as::<BlockValue>(CoapOption::Block2, block);
+            response.payload = vec![0; 16];
+            Some(response)
+        })
+        .await;
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let (_, mut stream) = client
+            .send_streaming(RequestBuilder::new("/unavailable", Method::Get).build())
+            .await
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_ok());
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::ResponseStatus {
+                status: Status::ServiceUnavailable,
+                ..
+            }
+        ));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_nstart_queues_requests() {
+        let server_port = spawn_server("127.0.0.1:0", wait_handler)
+            .recv()
+            .await
+            .unwrap();
+
+        let client = Arc::new(
+            UdpCoAPClient::new_udp(format!("127.0.0.1:{}", server_port))
+                .await
+                .unwrap(),
+        );
+        let start = Instant::now();
+        let (a, b) = tokio::join!(
+            do_wait_request(client.clone(), "/a", vec![1], 300),
+            do_wait_request(client.clone(), "/b", vec![2], 300)
+        );
+        a.unwrap();
+        b.unwrap();
+        assert!(
+            start.elapsed() >= Duration::from_millis(600),
+            "requests were not sent one after another"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nstart_released_by_empty_ack() {
+        let server = Arc::new(TestPeer::bind().await);
+        let server_addr = server.local_addr();
+        tokio::spawn(async move {
+            loop {
+                let (request, client_addr) = server.recv().await;
+                let mut response = ack(&request, Status::Content);
+                if request.get_first_option(CoapOption::UriPath) == Some(&b"fast".to_vec()) {
+                    server.send(&response, client_addr).await;
+                    continue;
+                }
+                // acknowledge the slow request right away and respond later
+                let mut empty_ack = Message::new();
+                empty_ack.header.set_type(MessageType::Acknowledgement);
+                empty_ack.header.code = MessageClass::Empty;
+                empty_ack.header.message_id = request.header.message_id;
+                server.send(&empty_ack, client_addr).await;
+                let server = server.clone();
+                tokio::spawn(async move {
+                    time::sleep(Duration::from_secs(1)).await;
+                    response.header.set_type(MessageType::NonConfirmable);
+                    response.header.message_id = 4242;
+                    server.send(&response, client_addr).await;
+                });
+            }
+        });
+
+        let client = UdpCoAPClient::new_udp(server_addr).await.unwrap();
+        let start = Instant::now();
+        let (slow, fast) = tokio::join!(
+            client.send(RequestBuilder::new("/slow", Method::Get).build()),
+            async {
+                time::sleep(Duration::from_millis(100)).await;
+                let response = client.send(RequestBuilder::new("/fast", Method::Get).build());
+                let response = response.await;
+                (response, start.elapsed())
+            }
+        );
+        slow.unwrap();
+        let (fast, elapsed) = fast;
+        fast.unwrap();
+        assert!(
+            elapsed < Duration::from_millis(800),
+            "the separate response held the permit"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generated_tokens() {
+        let server_port = spawn_server("127.0.0.1:0", wait_handler)
+            .recv()
+            .await
+            .unwrap();
+
+        let mut client = UdpCoAPClient::new_udp(format!("127.0.0.1:{}", server_port))
+            .await
+            .unwrap();
+        client.set_token_length(8);
+        client.set_transmission_parameters(TransmissionParameters {
+            nstart: 2,
+            ..Default::default()
+        });
+        let request = |path: &str| {
+            let mut request = RequestBuilder::new(path, Method::Get).build();
+            request.message.payload = b"100".to_vec();
+            request
+        };
+        let (a, b) = tokio::join!(client.send(request("/a")), client.send(request("/b")));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_eq!(a.message.payload, b"a".to_vec());
+        assert_eq!(b.message.payload, b"b".to_vec());
+        assert_eq!(a.message.get_token().len(), 8);
+        assert_ne!(a.message.get_token(), b.message.get_token());
+
+        client.set_token_length(20);
+        let c = client.send(request("/c")).await.unwrap();
+        assert_eq!(c.message.get_token().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_token_in_flight_is_refused() {
+        let synchronizer = TransportSynchronizer::new();
+        let (tx, _rx) = unbounded_channel();
+        synchronizer
+            .try_set_sender(vec![1], tx.clone())
+            .await
+            .unwrap();
+        let error = synchronizer.try_set_sender(vec![1], tx).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    }
+
+    #[tokio::test]
+    async fn test_generated_tokens_are_reserved() {
+        let synchronizer = TransportSynchronizer::new();
+        let mut tokens = HashSet::new();
+        for _ in 0..200 {
+            assert!(tokens.insert(synchronizer.generate_token(1).await));
+        }
+        let reserved = tokens.iter().next().unwrap().clone();
+        let (tx, _rx) = unbounded_channel();
+        synchronizer
+            .try_set_sender(reserved.clone(), tx)
+            .await
+            .unwrap();
+        synchronizer.release_token(&reserved).await;
+        assert!(synchronizer.get_sender(&reserved).await.is_some());
+        for token in &tokens {
+            synchronizer.release_token(token).await;
+        }
+        assert_eq!(synchronizer.outgoing.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handover_keeps_the_token_routed() {
+        let synchronizer = TransportSynchronizer::new();
+        let (tx_observe, _rx_observe) = unbounded_channel();
+        synchronizer
+            .start_handover(vec![1], tx_observe.clone())
+            .await
+            .unwrap();
+        // the exchanges of the registration use the token one after the other
+        for _ in 0..2 {
+            let (tx, _rx) = unbounded_channel();
+            synchronizer
+                .try_set_sender(vec![1], tx.clone())
+                .await
+                .unwrap();
+            let sender = synchronizer.get_sender(&[1]).await.unwrap();
+            assert!(sender.same_channel(&tx));
+            synchronizer.remove_sender(&[1]).await;
+            let sender = synchronizer.get_sender(&[1]).await.unwrap();
+            assert!(sender.same_channel(&tx_observe));
+        }
+        synchronizer.finish_handover(&[1]).await;
+        let (tx, _rx) = unbounded_channel();
+        let error = synchronizer.try_set_sender(vec![1], tx).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        synchronizer.remove_sender(&[1]).await;
+        assert!(synchronizer.get_sender(&[1]).await.is_none());
     }
 
     struct FaultyReceiver {
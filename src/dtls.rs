@@ -177,7 +177,7 @@ impl Drop for DtlsConnection {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::client::CoAPClient;
+    use crate::client::{ClientConfig, ClientError, CoAPClient};
     use crate::request::RequestBuilder;
     use crate::server::UdpCoapListener;
     use crate::{Server, UdpCoAPClient};
@@ -382,6 +382,29 @@ mod test {
             .unwrap();
         assert_eq!(resp.message.payload, b"hello".to_vec());
     }
+    #[tokio::test]
+    async fn test_coaps_url() {
+        let config = get_psk_config();
+        let server_port = spawn_dtls_server("127.0.0.1:0", request_handler, config.clone())
+            .recv()
+            .await
+            .unwrap();
+
+        let client_config = ClientConfig {
+            dtls_config: Some(config),
+            ..Default::default()
+        };
+        let resp = UdpCoAPClient::request_with_config(
+            &format!("coaps://127.0.0.1:{}/hello", server_port),
+            Method::Get,
+            None,
+            &client_config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.message.payload, b"hello".to_vec());
+    }
+
     #[tokio::test]
     async fn test_dtls_psk() {
         let config = get_psk_config();
@@ -457,7 +480,7 @@ mod test {
         )
         .await;
         let get_error = get.unwrap_err();
-        assert!(get_error.kind() == ErrorKind::TimedOut);
+        assert!(matches!(get_error, ClientError::Timeout));
 
         let dtls_config = UdpDtlsConfig {
             config,
@@ -10,6 +10,7 @@
 //! - Block-Wise Transfers [RFC 7959](https://tools.ietf.org/html/rfc7959)
 //! - DTLS support via [webrtc-rs](https://github.com/webrtc-rs/webrtc)
 //! - Option to provide custom transports for client and server
+//! - Typed JSON and CBOR payloads with the optional `json` and `cbor` features
 //! - Client can perform multiple concurrent requests, like observing and sending requests using
 //! the same underlying socket
 //!
@@ -90,12 +91,18 @@ extern crate alloc;
 #[cfg(test)]
 extern crate quickcheck;
 
+pub use self::agent::CoapAgent;
 pub use self::client::UdpCoAPClient;
 pub use self::observer::Observer;
 pub use self::server::Server;
+pub mod agent;
+pub mod cache;
 pub mod client;
 #[cfg(feature = "dtls")]
 pub mod dtls;
+pub mod link_format;
 mod observer;
+pub mod payload;
 pub mod request;
+pub mod response;
 pub mod server;
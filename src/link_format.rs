@@ -0,0 +1,311 @@
+//! Parsing of the CoRE Link Format (RFC 6690) served by `/.well-known/core`
+
+use std::fmt;
+
+use crate::client::ClientError;
+
+/// A link to a resource with its target attributes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Link {
+    /// the URI-reference between the angle brackets, e.g. `/sensors/temp`
+    pub target: String,
+    /// the resource types, `rt`
+    pub resource_types: Vec<String>,
+    /// the interface descriptions, `if`
+    pub interfaces: Vec<String>,
+    /// the content formats the resource is available in, `ct`
+    pub content_formats: Vec<u16>,
+    /// the estimated maximum size of the resource, `sz`
+    pub size: Option<u64>,
+    /// whether the resource is observable, `obs`
+    pub observable: bool,
+    /// all attributes in the order they appear, including the ones above
+    pub attributes: Vec<(String, Option<String>)>,
+}
+
+impl Link {
+    /// Whether the link matches a query filter as described in RFC 6690 section 4.1, e.g.
+    /// `rt=temperature` or `href=/sensors*`. A trailing `*` matches any value with the
+    /// prefix. Filters without a value match links which have the attribute
+    pub fn matches(&self, filter: &str) -> bool {
+        let (name, pattern) = match filter.split_once('=') {
+            Some((name, pattern)) => (name, Some(pattern)),
+            None => (filter, None),
+        };
+        let value_matches = |value: &str| match pattern {
+            None => true,
+            Some(pattern) => match pattern.strip_suffix('*') {
+                Some(prefix) => value.starts_with(prefix),
+                None => value == pattern,
+            },
+        };
+        if name == "href" {
+            return value_matches(&self.target);
+        }
+        self.attributes
+            .iter()
+            .filter(|(attribute, _)| attribute == name)
+            .any(|(_, value)| match value {
+                // rt, if and ct may hold several space separated values
+                Some(value) => value_matches(value) || value.split_whitespace().any(value_matches),
+                None => pattern.is_none(),
+            })
+    }
+
+    fn add_attribute(&mut self, name: &str, value: Option<String>) -> Result<(), &'static str> {
+        let values = value.as_deref().unwrap_or_default().split_whitespace();
+        match name {
+            "rt" => self.resource_types.extend(values.map(String::from)),
+            "if" => self.interfaces.extend(values.map(String::from)),
+            "ct" => {
+                for content_format in values {
+                    let content_format = content_format
+                        .parse()
+                        .map_err(|_| "invalid content format")?;
+                    self.content_formats.push(content_format);
+                }
+            }
+            "sz" => {
+                let size = value.as_deref().and_then(|size| size.parse().ok());
+                self.size = Some(size.ok_or("invalid size")?);
+            }
+            "obs" => self.observable = true,
+            _ => {}
+        }
+        self.attributes.push((name.to_string(), value));
+        Ok(())
+    }
+}
+
+/// An error in a link-format document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkFormatError {
+    /// the byte offset of the error in the document
+    pub position: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for LinkFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid link format at byte {}: {}",
+            self.position, self.reason
+        )
+    }
+}
+
+impl std::error::Error for LinkFormatError {}
+
+impl From<LinkFormatError> for ClientError {
+    fn from(error: LinkFormatError) -> Self {
+        ClientError::MalformedResponse(error.to_string())
+    }
+}
+
+/// Parse a link-format document into its links
+///
+/// # Examples
+///
+/// ```
+/// use coap::link_format::parse;
+///
+/// let links = parse(r#"</sensors/temp>;rt="temperature-c";if="sensor";obs,</light>;ct=0"#).unwrap();
+/// assert_eq!(links[0].target, "/sensors/temp");
+/// assert_eq!(links[0].resource_types, vec!["temperature-c"]);
+/// assert!(links[0].observable);
+/// assert_eq!(links[1].content_formats, vec![0]);
+/// ```
+pub fn parse(document: &str) -> Result<Vec<Link>, LinkFormatError> {
+    let mut parser = Parser {
+        document,
+        position: 0,
+    };
+    let mut links = vec![];
+    parser.skip_whitespace();
+    if parser.peek().is_none() {
+        return Ok(links);
+    }
+    loop {
+        links.push(parser.link()?);
+        parser.skip_whitespace();
+        match parser.next() {
+            None => return Ok(links),
+            Some(',') => parser.skip_whitespace(),
+            Some(_) => return Err(parser.error("expected ','")),
+        }
+    }
+}
+
+struct Parser<'a> {
+    document: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.document[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, reason: &'static str) -> LinkFormatError {
+        LinkFormatError {
+            position: self.position,
+            reason,
+        }
+    }
+
+    fn expect(&mut self, expected: char, reason: &'static str) -> Result<(), LinkFormatError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += c.len_utf8();
+                Ok(())
+            }
+            _ => Err(self.error(reason)),
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let start = self.position;
+        while self.peek().is_some_and(&predicate) {
+            self.next();
+        }
+        &self.document[start..self.position]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn link(&mut self) -> Result<Link, LinkFormatError> {
+        self.expect('<', "expected '<'")?;
+        let target = self.take_while(|c| c != '>');
+        self.expect('>', "unterminated link target")?;
+        let mut link = Link {
+            target: target.to_string(),
+            ..Default::default()
+        };
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(';') {
+                return Ok(link);
+            }
+            self.next();
+            self.skip_whitespace();
+            let name =
+                self.take_while(|c| !matches!(c, '=' | ';' | ',' | '"') && !c.is_whitespace());
+            if name.is_empty() {
+                return Err(self.error("expected an attribute name"));
+            }
+            self.skip_whitespace();
+            let value = match self.peek() {
+                Some('=') => {
+                    self.next();
+                    self.skip_whitespace();
+                    Some(self.value()?)
+                }
+                _ => None,
+            };
+            let position = self.position;
+            link.add_attribute(name, value)
+                .map_err(|reason| LinkFormatError { position, reason })?;
+        }
+    }
+
+    /// a token or a quoted string
+    fn value(&mut self) -> Result<String, LinkFormatError> {
+        if self.peek() != Some('"') {
+            let token = self.take_while(|c| !matches!(c, ';' | ',' | '"') && !c.is_whitespace());
+            if token.is_empty() {
+                return Err(self.error("expected a value"));
+            }
+            return Ok(token.to_string());
+        }
+        self.next();
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("unterminated quoted string")),
+                Some('"') => return Ok(value),
+                Some('\\') => match self.next() {
+                    Some(c) => value.push(c),
+                    None => return Err(self.error("unterminated quoted string")),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let document =
+            "</sensors/temp>;rt=\"temperature-c core.s\";if=sensor;ct=\"0 50\";sz=64;obs,\n\
+                        </sensors/light>;title=\"Light, \\\"lux\\\"; indoor\";anchor=\"/sensors\"";
+        let links = parse(document).unwrap();
+        assert_eq!(links.len(), 2);
+
+        let temp = &links[0];
+        assert_eq!(temp.target, "/sensors/temp");
+        assert_eq!(temp.resource_types, vec!["temperature-c", "core.s"]);
+        assert_eq!(temp.interfaces, vec!["sensor"]);
+        assert_eq!(temp.content_formats, vec![0, 50]);
+        assert_eq!(temp.size, Some(64));
+        assert!(temp.observable);
+        assert_eq!(temp.attributes.len(), 5);
+        assert_eq!(temp.attributes[4], ("obs".to_string(), None));
+
+        let light = &links[1];
+        assert_eq!(light.target, "/sensors/light");
+        assert!(!light.observable);
+        assert_eq!(
+            light.attributes,
+            vec![
+                (
+                    "title".to_string(),
+                    Some("Light, \"lux\"; indoor".to_string())
+                ),
+                ("anchor".to_string(), Some("/sensors".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("").unwrap(), vec![]);
+        assert_eq!(parse("/a").unwrap_err().position, 0);
+        assert_eq!(parse("</a").unwrap_err().reason, "unterminated link target");
+        assert_eq!(
+            parse("</a>;title=\"x").unwrap_err().reason,
+            "unterminated quoted string"
+        );
+        assert_eq!(
+            parse("</a>;ct=json").unwrap_err().reason,
+            "invalid content format"
+        );
+        assert_eq!(parse("</a> </b>").unwrap_err().reason, "expected ','");
+    }
+
+    #[test]
+    fn test_matches() {
+        let links = parse("</sensors/temp>;rt=\"temperature-c core.s\";ct=0;obs").unwrap();
+        let link = &links[0];
+        assert!(link.matches("rt=temperature-c"));
+        assert!(link.matches("rt=core.s"));
+        assert!(link.matches("rt=temp*"));
+        assert!(link.matches("href=/sensors/*"));
+        assert!(link.matches("ct=0"));
+        assert!(link.matches("obs"));
+        assert!(!link.matches("rt=temperature"));
+        assert!(!link.matches("if=sensor"));
+    }
+}
@@ -100,6 +100,12 @@ impl Observer {
                 self.resource_changed(request).await;
                 return true;
             }
+            // the payload of a PATCH or iPATCH only describes the changes (RFC 8132), so the
+            // kept representation stays as it is
+            (&Method::Patch | &Method::IPatch, _) => {
+                self.resource_patched(request).await;
+                return true;
+            }
             _ => return true,
         }
     }
@@ -197,6 +203,23 @@ impl Observer {
                 .collect();
         }
 
+        self.notify_registers(register_resource_keys).await;
+    }
+
+    async fn resource_patched(&mut self, request: &CoapRequest<SocketAddr>) {
+        let resource_path = request.get_path();
+
+        debug!("resource_patched {}", resource_path);
+
+        let Some(resource) = self.resources.get_mut(&resource_path) else {
+            return;
+        };
+        resource.sequence += 1;
+        let register_resource_keys = resource.register_resources.iter().cloned().collect();
+        self.notify_registers(register_resource_keys).await;
+    }
+
+    async fn notify_registers(&mut self, register_resource_keys: Vec<String>) {
         for register_resource_key in register_resource_keys {
             self.gen_message_id();
             self.notify_register_with_newest_resource(&register_resource_key)
@@ -428,7 +451,8 @@ mod test {
 
     use super::super::*;
     use super::*;
-    use std::{io::ErrorKind, time::Duration};
+    use crate::client::ClientError;
+    use std::time::Duration;
     use tokio::sync::mpsc;
 
     async fn request_handler(
@@ -439,7 +463,9 @@ mod test {
                 let observe_option = req.get_observe_flag().unwrap().unwrap();
                 assert_eq!(observe_option, ObserveOption::Deregister);
             }
-            &coap_lite::RequestType::Put => {}
+            &coap_lite::RequestType::Put
+            | &coap_lite::RequestType::Patch
+            | &coap_lite::RequestType::IPatch => {}
             _ => panic!("unexpected request"),
         }
 
@@ -521,6 +547,45 @@ mod test {
             Some(())
         );
     }
+    #[tokio::test]
+    async fn test_observe_patch() {
+        let path = "/test";
+        let server_port = server::test::spawn_server("127.0.0.1:0", request_handler)
+            .recv()
+            .await
+            .unwrap();
+
+        let client = UdpCoAPClient::new_udp(format!("127.0.0.1:{}", server_port))
+            .await
+            .unwrap();
+        let request = RequestBuilder::new(path, coap_lite::RequestType::Put)
+            .data(Some(b"data1".to_vec()))
+            .build();
+        client.send(request).await.unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let _observe = client
+            .observe(path, move |msg| tx.send(msg.payload).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), b"data1".to_vec());
+
+        // the patch documents are not taken as the representation
+        for method in [
+            coap_lite::RequestType::Patch,
+            coap_lite::RequestType::IPatch,
+        ] {
+            let request = RequestBuilder::new(path, method)
+                .data(Some(b"patch".to_vec()))
+                .build();
+            client.send(request).await.unwrap();
+            let notification = tokio::time::timeout(Duration::new(5, 0), rx.recv())
+                .await
+                .unwrap();
+            assert_eq!(notification, Some(b"data1".to_vec()));
+        }
+    }
+
     #[tokio::test]
     async fn test_unobserve() {
         let path = "/test";
@@ -571,6 +636,12 @@ mod test {
             .await
             .unwrap();
         let error = client.observe(path, |_msg| {}).await.unwrap_err();
-        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(matches!(
+            error,
+            ClientError::ResponseStatus {
+                status: Status::NotFound,
+                ..
+            }
+        ));
     }
 }
@@ -0,0 +1,323 @@
+//! Typed payloads encoded according to their Content-Format. The JSON helpers need the `json`
+//! feature, the CBOR helpers the `cbor` feature
+
+use std::fmt;
+
+#[cfg(any(feature = "json", feature = "cbor"))]
+use coap_lite::{CoapOption, CoapRequest, Packet};
+#[cfg(any(feature = "json", feature = "cbor"))]
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(any(feature = "json", feature = "cbor"))]
+use std::net::SocketAddr;
+
+use crate::client::ClientError;
+#[cfg(any(feature = "json", feature = "cbor"))]
+use crate::client::{decode_uint, encode_uint, ClientTransport, CoAPClient};
+#[cfg(any(feature = "json", feature = "cbor"))]
+use crate::response::ResponseExt;
+
+/// the Content-Format of application/json
+pub const CONTENT_FORMAT_JSON: u16 = 50;
+/// the Content-Format of application/cbor
+pub const CONTENT_FORMAT_CBOR: u16 = 60;
+
+/// An error encoding or decoding a typed payload
+#[derive(Debug)]
+pub enum PayloadError {
+    /// the Content-Format is none of the enabled formats, `None` if it is missing or longer
+    /// than two bytes
+    UnsupportedContentFormat(Option<u16>),
+    /// the value could not be encoded
+    Encode(String),
+    /// the payload could not be decoded into the type
+    Decode(String),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::UnsupportedContentFormat(Some(content_format)) => {
+                write!(f, "unsupported content format {}", content_format)
+            }
+            PayloadError::UnsupportedContentFormat(None) => {
+                write!(f, "missing or invalid content format")
+            }
+            PayloadError::Encode(reason) => write!(f, "could not encode payload: {}", reason),
+            PayloadError::Decode(reason) => write!(f, "could not decode payload: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+impl From<PayloadError> for ClientError {
+    fn from(e: PayloadError) -> Self {
+        ClientError::Payload(e)
+    }
+}
+
+/// Typed payloads for the messages of requests and responses. Server handlers use it on
+/// `request.message` and `response.message` just like clients do
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "json")]
+/// # {
+/// use coap::payload::PayloadExt;
+/// use coap_lite::Packet;
+///
+/// let mut message = Packet::new();
+/// message.set_json(&vec![21.5, 22.0]).unwrap();
+/// let temperatures: Vec<f64> = message.decode().unwrap();
+/// assert_eq!(temperatures, vec![21.5, 22.0]);
+/// # }
+/// ```
+#[cfg(any(feature = "json", feature = "cbor"))]
+pub trait PayloadExt {
+    /// encodes the value as the JSON payload and sets the Content-Format
+    #[cfg(feature = "json")]
+    fn set_json<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PayloadError>;
+    /// decodes the payload as JSON, whatever the Content-Format
+    #[cfg(feature = "json")]
+    fn json<T: DeserializeOwned>(&self) -> Result<T, PayloadError>;
+    /// encodes the value as the CBOR payload and sets the Content-Format
+    #[cfg(feature = "cbor")]
+    fn set_cbor<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PayloadError>;
+    /// decodes the payload as CBOR, whatever the Content-Format
+    #[cfg(feature = "cbor")]
+    fn cbor<T: DeserializeOwned>(&self) -> Result<T, PayloadError>;
+    /// decodes the payload according to its Content-Format
+    fn decode<T: DeserializeOwned>(&self) -> Result<T, PayloadError>;
+}
+
+#[cfg(any(feature = "json", feature = "cbor"))]
+impl PayloadExt for Packet {
+    #[cfg(feature = "json")]
+    fn set_json<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PayloadError> {
+        self.payload =
+            serde_json::to_vec(value).map_err(|e| PayloadError::Encode(e.to_string()))?;
+        set_uint_option(self, CoapOption::ContentFormat, CONTENT_FORMAT_JSON);
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    fn json<T: DeserializeOwned>(&self) -> Result<T, PayloadError> {
+        serde_json::from_slice(&self.payload).map_err(|e| PayloadError::Decode(e.to_string()))
+    }
+
+    #[cfg(feature = "cbor")]
+    fn set_cbor<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), PayloadError> {
+        let mut payload = vec![];
+        ciborium::ser::into_writer(value, &mut payload)
+            .map_err(|e| PayloadError::Encode(e.to_string()))?;
+        self.payload = payload;
+        set_uint_option(self, CoapOption::ContentFormat, CONTENT_FORMAT_CBOR);
+        Ok(())
+    }
+
+    #[cfg(feature = "cbor")]
+    fn cbor<T: DeserializeOwned>(&self) -> Result<T, PayloadError> {
+        ciborium::de::from_reader(self.payload.as_slice())
+            .map_err(|e| PayloadError::Decode(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self) -> Result<T, PayloadError> {
+        let content_format = uint16_option(self, CoapOption::ContentFormat);
+        match content_format {
+            #[cfg(feature = "json")]
+            Some(CONTENT_FORMAT_JSON) => self.json(),
+            #[cfg(feature = "cbor")]
+            Some(CONTENT_FORMAT_CBOR) => self.cbor(),
+            other => Err(PayloadError::UnsupportedContentFormat(other)),
+        }
+    }
+}
+
+#[cfg(any(feature = "json", feature = "cbor"))]
+fn set_uint_option(message: &mut Packet, option: CoapOption, value: u16) {
+    message.clear_option(option);
+    message.add_option(option, encode_uint(value.into()));
+}
+
+/// the value of a Content-Format or Accept option, `None` if it is missing or does not fit
+/// in two bytes
+#[cfg(any(feature = "json", feature = "cbor"))]
+fn uint16_option(message: &Packet, option: CoapOption) -> Option<u16> {
+    let value = message.get_first_option(option)?;
+    u16::try_from(decode_uint(value)).ok()
+}
+
+#[cfg(any(feature = "json", feature = "cbor"))]
+impl<T: ClientTransport + 'static> CoAPClient<T> {
+    /// Send the request with the body encoded as JSON and JSON as the accepted format, and
+    /// decode the response according to its Content-Format. Error statuses are returned as
+    /// `ClientError::ResponseStatus`
+    #[cfg(feature = "json")]
+    pub async fn send_json<B, R>(
+        &self,
+        mut request: CoapRequest<SocketAddr>,
+        body: &B,
+    ) -> Result<R, ClientError>
+    where
+        B: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        request.message.set_json(body)?;
+        self.send_typed(request, CONTENT_FORMAT_JSON).await
+    }
+
+    /// Same as `send_json` for requests without a body, like GET. The payload of the request
+    /// is sent as it is
+    #[cfg(feature = "json")]
+    pub async fn receive_json<R: DeserializeOwned>(
+        &self,
+        request: CoapRequest<SocketAddr>,
+    ) -> Result<R, ClientError> {
+        self.send_typed(request, CONTENT_FORMAT_JSON).await
+    }
+
+    /// Same as `send_json`, with CBOR
+    #[cfg(feature = "cbor")]
+    pub async fn send_cbor<B, R>(
+        &self,
+        mut request: CoapRequest<SocketAddr>,
+        body: &B,
+    ) -> Result<R, ClientError>
+    where
+        B: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        request.message.set_cbor(body)?;
+        self.send_typed(request, CONTENT_FORMAT_CBOR).await
+    }
+
+    /// Same as `receive_json`, with CBOR
+    #[cfg(feature = "cbor")]
+    pub async fn receive_cbor<R: DeserializeOwned>(
+        &self,
+        request: CoapRequest<SocketAddr>,
+    ) -> Result<R, ClientError> {
+        self.send_typed(request, CONTENT_FORMAT_CBOR).await
+    }
+
+    async fn send_typed<R: DeserializeOwned>(
+        &self,
+        mut request: CoapRequest<SocketAddr>,
+        accept: u16,
+    ) -> Result<R, ClientError> {
+        set_uint_option(&mut request.message, CoapOption::Accept, accept);
+        let response = self.send(request).await?.error_for_status()?;
+        Ok(response.message.decode()?)
+    }
+}
+
+#[cfg(all(test, any(feature = "json", feature = "cbor")))]
+mod test {
+    use super::*;
+    use crate::client::UdpCoAPClient;
+    use crate::request::RequestBuilder;
+    use crate::server::test::{ack, spawn_udp_server};
+    use coap_lite::{RequestType as Method, ResponseType as Status};
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        sensor: String,
+        value: f64,
+    }
+
+    fn reading() -> Reading {
+        Reading {
+            sensor: "temp".to_string(),
+            value: 21.5,
+        }
+    }
+
+    /// answers with the reading in the accepted format, or 4.15 for other request formats
+    async fn spawn_reading_server() -> UdpCoAPClient {
+        let server_addr = spawn_udp_server(|request, _| {
+            let reading: Result<Reading, _> = request.decode();
+            let accept = uint16_option(&request, CoapOption::Accept);
+            let mut response = ack(&request, Status::Content);
+            match (reading, accept) {
+                #[cfg(feature = "json")]
+                (Ok(reading), Some(CONTENT_FORMAT_JSON)) => response.set_json(&reading).unwrap(),
+                #[cfg(feature = "cbor")]
+                (Ok(reading), Some(CONTENT_FORMAT_CBOR)) => response.set_cbor(&reading).unwrap(),
+                _ => return Some(ack(&request, Status::UnsupportedContentFormat)),
+            }
+            Some(response)
+        })
+        .await;
+        UdpCoAPClient::new_udp(server_addr).await.unwrap()
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn test_send_json() {
+        let client = spawn_reading_server().await;
+        let request = RequestBuilder::new("/echo", Method::Post).build();
+        let echo: Reading = client.send_json(request, &reading()).await.unwrap();
+        assert_eq!(echo, reading());
+
+        let request = RequestBuilder::new("/echo", Method::Post).build();
+        let error = client.receive_json::<Reading>(request).await.unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::ResponseStatus {
+                status: Status::UnsupportedContentFormat,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[tokio::test]
+    async fn test_send_cbor() {
+        let client = spawn_reading_server().await;
+        let request = RequestBuilder::new("/echo", Method::Post).build();
+        let echo: Reading = client.send_cbor(request, &reading()).await.unwrap();
+        assert_eq!(echo, reading());
+
+        let request = RequestBuilder::new("/echo", Method::Post).build();
+        let error = client.receive_cbor::<Reading>(request).await.unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::ResponseStatus {
+                status: Status::UnsupportedContentFormat,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut message = Packet::new();
+        message.payload = b"{}".to_vec();
+        assert!(matches!(
+            message.decode::<Reading>(),
+            Err(PayloadError::UnsupportedContentFormat(None))
+        ));
+        set_uint_option(&mut message, CoapOption::ContentFormat, 0);
+        assert!(matches!(
+            message.decode::<Reading>(),
+            Err(PayloadError::UnsupportedContentFormat(Some(0)))
+        ));
+        // 0x10032 would be JSON when truncated to two bytes
+        message.clear_option(CoapOption::ContentFormat);
+        message.add_option(CoapOption::ContentFormat, vec![0x01, 0x00, 0x32]);
+        assert!(matches!(
+            message.decode::<Reading>(),
+            Err(PayloadError::UnsupportedContentFormat(None))
+        ));
+        #[cfg(feature = "json")]
+        {
+            set_uint_option(&mut message, CoapOption::ContentFormat, CONTENT_FORMAT_JSON);
+            assert!(matches!(
+                message.decode::<Reading>(),
+                Err(PayloadError::Decode(_))
+            ));
+        }
+    }
+}
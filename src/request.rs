@@ -17,6 +17,8 @@ pub struct RequestBuilder<'a> {
     options: Vec<(CoapOption, Vec<u8>)>,
 }
 impl<'a> RequestBuilder<'a> {
+    /// Create a new request with the given path and method. FETCH, PATCH and iPATCH requests
+    /// carry their body in `data` like POST and PUT
     pub fn new(path: &'a str, method: Method) -> Self {
         Self {
             path,
@@ -126,6 +128,17 @@ pub mod test {
         assert_eq!(build.message.payload.as_slice(), b"hello, world!");
     }
     #[test]
+    fn test_fetch_request() {
+        for method in [Method::Fetch, Method::Patch, Method::IPatch] {
+            let build = RequestBuilder::new("/query", method)
+                .data(Some(b"{\"unit\":\"c\"}".to_vec()))
+                .build();
+            assert_eq!(*build.get_method(), method);
+            assert_eq!(build.message.header.code, MessageClass::Request(method));
+            assert_eq!(build.message.payload.as_slice(), b"{\"unit\":\"c\"}");
+        }
+    }
+    #[test]
     fn test_domain() {
         let build = RequestBuilder::request_path(
             "/",
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+pub use coap_lite::{
+    CoapOption, CoapResponse, ContentFormat, MessageClass, ResponseType as Status,
+};
+
+use crate::client::{self, ClientError};
+
+/// Typed accessors and status checks for the responses returned by the client
+pub trait ResponseExt: Sized {
+    /// the Content-Format of the payload
+    fn content_format(&self) -> Option<ContentFormat>;
+    /// the ETag of the representation
+    fn etag(&self) -> Option<&[u8]>;
+    /// how long the response may be cached, 60 seconds if the server did not say
+    fn max_age(&self) -> Duration;
+    /// the Location-Path of a created resource, segments are joined with '/'
+    fn location_path(&self) -> Option<String>;
+    /// the sequence number of a notification
+    fn observe(&self) -> Option<u32>;
+    /// whether the response has a 2.xx code
+    fn is_success(&self) -> bool;
+    /// turns 4.xx and 5.xx responses into a `ClientError::ResponseStatus` carrying the
+    /// diagnostic payload
+    fn error_for_status(self) -> Result<Self, ClientError>;
+}
+
+impl ResponseExt for CoapResponse {
+    fn content_format(&self) -> Option<ContentFormat> {
+        self.message.get_content_format()
+    }
+
+    fn etag(&self) -> Option<&[u8]> {
+        self.message
+            .get_first_option(CoapOption::ETag)
+            .map(|etag| etag.as_slice())
+    }
+
+    fn max_age(&self) -> Duration {
+        client::max_age(&self.message)
+    }
+
+    fn location_path(&self) -> Option<String> {
+        let segments = self.message.get_option(CoapOption::LocationPath)?;
+        let segments: Vec<_> = segments
+            .iter()
+            .map(|segment| String::from_utf8_lossy(segment))
+            .collect();
+        Some(segments.join("/"))
+    }
+
+    fn observe(&self) -> Option<u32> {
+        self.message.get_observe_value().and_then(|x| x.ok())
+    }
+
+    fn is_success(&self) -> bool {
+        client::is_success(&self.message)
+    }
+
+    fn error_for_status(self) -> Result<Self, ClientError> {
+        match self.message.header.code {
+            code @ MessageClass::Response(_) if u8::from(code) >> 5 >= 4 => {
+                Err(ClientError::response_status(&self.message))
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use coap_lite::Packet;
+
+    fn response(status: Status) -> CoapResponse {
+        let mut message = Packet::new();
+        message.header.code = MessageClass::Response(status);
+        CoapResponse { message }
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut resp = response(Status::Created);
+        resp.message
+            .set_content_format(ContentFormat::ApplicationJSON);
+        resp.message.add_option(CoapOption::ETag, vec![1, 2]);
+        resp.message
+            .add_option(CoapOption::MaxAge, vec![0x01, 0x2c]);
+        resp.message
+            .add_option(CoapOption::LocationPath, b"sensors".to_vec());
+        resp.message
+            .add_option(CoapOption::LocationPath, b"7".to_vec());
+        resp.message.set_observe_value(42);
+
+        assert_eq!(resp.content_format(), Some(ContentFormat::ApplicationJSON));
+        assert_eq!(resp.etag(), Some(&[1, 2][..]));
+        assert_eq!(resp.max_age(), Duration::from_secs(300));
+        assert_eq!(resp.location_path().as_deref(), Some("sensors/7"));
+        assert_eq!(resp.observe(), Some(42));
+        assert!(resp.is_success());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let resp = response(Status::Content);
+        assert_eq!(resp.content_format(), None);
+        assert_eq!(resp.etag(), None);
+        assert_eq!(resp.max_age(), Duration::from_secs(60));
+        assert_eq!(resp.location_path(), None);
+        assert_eq!(resp.observe(), None);
+    }
+
+    #[test]
+    fn test_oversized_max_age() {
+        let mut resp = response(Status::Content);
+        resp.message.add_option(CoapOption::MaxAge, vec![0xff; 8]);
+        assert_eq!(resp.max_age(), Duration::from_secs(u32::MAX.into()));
+    }
+
+    #[test]
+    fn test_error_for_status() {
+        assert!(response(Status::Content).error_for_status().is_ok());
+
+        let mut not_found = response(Status::NotFound);
+        not_found.message.payload = b"no such sensor".to_vec();
+        match not_found.error_for_status() {
+            Err(ClientError::ResponseStatus { status, payload }) => {
+                assert_eq!(status, Status::NotFound);
+                assert_eq!(payload, b"no such sensor");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            response(Status::InternalServerError).error_for_status(),
+            Err(ClientError::ResponseStatus {
+                status: Status::InternalServerError,
+                ..
+            })
+        ));
+    }
+}
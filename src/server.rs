@@ -449,7 +449,9 @@ pub mod test {
 
     use super::super::*;
     use super::*;
-    use coap_lite::{block_handler::BlockValue, CoapOption, RequestType};
+    use coap_lite::{
+        block_handler::BlockValue, CoapOption, MessageClass, MessageType, RequestType, ResponseType,
+    };
     use std::str;
     use std::time::Duration;
 
@@ -476,6 +478,66 @@ pub mod test {
         rx
     }
 
+    /// A bare UDP peer for tests which need full control over the messages a client
+    /// exchanges with a server
+    pub struct TestPeer {
+        socket: UdpSocket,
+    }
+
+    impl TestPeer {
+        pub async fn bind() -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            Self { socket }
+        }
+
+        pub fn local_addr(&self) -> SocketAddr {
+            self.socket.local_addr().unwrap()
+        }
+
+        /// the next message and the address it was sent from
+        pub async fn recv(&self) -> (Packet, SocketAddr) {
+            let mut buf = [0; 1500];
+            let (n, from) = self.socket.recv_from(&mut buf).await.unwrap();
+            (Packet::from_bytes(&buf[..n]).unwrap(), from)
+        }
+
+        pub async fn send(&self, message: &Packet, to: SocketAddr) {
+            self.socket
+                .send_to(&message.to_bytes().unwrap(), to)
+                .await
+                .unwrap();
+        }
+    }
+
+    /// the piggybacked response to the request with the status
+    pub fn ack(request: &Packet, status: ResponseType) -> Packet {
+        let mut response = Packet::new();
+        response.header.set_type(MessageType::Acknowledgement);
+        response.header.code = MessageClass::Response(status);
+        response.header.message_id = request.header.message_id;
+        response.set_token(request.get_token().to_vec());
+        response
+    }
+
+    /// Spawn a `TestPeer` passing every message it receives and its sender to `handler`, and
+    /// sending the reply back to the sender. Returns the address of the peer
+    pub async fn spawn_udp_server<F>(mut handler: F) -> SocketAddr
+    where
+        F: FnMut(Packet, SocketAddr) -> Option<Packet> + Send + 'static,
+    {
+        let peer = TestPeer::bind().await;
+        let addr = peer.local_addr();
+        tokio::spawn(async move {
+            loop {
+                let (request, from) = peer.recv().await;
+                if let Some(reply) = handler(request, from) {
+                    peer.send(&reply, from).await;
+                }
+            }
+        });
+        addr
+    }
+
     async fn request_handler(
         mut req: Box<CoapRequest<SocketAddr>>,
     ) -> Box<CoapRequest<SocketAddr>> {
@@ -971,4 +1033,26 @@ pub mod test {
             "responses do not match"
         );
     }
+
+    #[tokio::test]
+    async fn test_fetch_patch_ipatch() {
+        let server_port = spawn_server("127.0.0.1:0", |mut req| async {
+            let echo = format!("{:?} {}", req.get_method(), req.message.payload.len());
+            req.response.as_mut().unwrap().message.payload = echo.into_bytes();
+            req
+        })
+        .recv()
+        .await
+        .unwrap();
+        let url = format!("coap://127.0.0.1:{}/resource", server_port);
+
+        // larger than a block, sent with Block1
+        let query = vec![0xa1; 3000];
+        let resp = UdpCoAPClient::fetch(&url, query).await.unwrap();
+        assert_eq!(resp.message.payload, b"Fetch 3000".to_vec());
+        let resp = UdpCoAPClient::patch(&url, b"[]".to_vec()).await.unwrap();
+        assert_eq!(resp.message.payload, b"Patch 2".to_vec());
+        let resp = UdpCoAPClient::ipatch(&url, b"{}".to_vec()).await.unwrap();
+        assert_eq!(resp.message.payload, b"IPatch 2".to_vec());
+    }
 }